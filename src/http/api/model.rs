@@ -1,7 +1,9 @@
 use crate::http::Context;
+use crate::recommend::{diff as diff_models, pluck_model, Core, Inspection};
 use crate::storage::Store;
 use failure::Error;
 use rouille::{Request, Response};
+use uuid::Uuid;
 
 pub fn show(
     _request: &Request,
@@ -16,11 +18,137 @@ pub fn show(
     Ok(Response::json(&json!({ "result": model })))
 }
 
+/// Shows the weights of the part's current model, grouped by feature and
+/// sorted by magnitude.
+pub fn inspect(
+    _request: &Request,
+    which: String,
+    context: &Context<impl Store>,
+) -> Result<Response, Error> {
+    let model = pluck_model(context.storage.as_ref(), &which)?;
+    Ok(Response::json(&json!({ "result": Inspection::of(&model) })))
+}
+
+/// Shows how the weights changed from one version of the part's model to
+/// another.
+pub fn diff(
+    _request: &Request,
+    which: String,
+    from: Uuid,
+    to: Uuid,
+    context: &Context<impl Store>,
+) -> Result<Response, Error> {
+    let from = context.storage.model_version_find(&which, from)?;
+    let to = context.storage.model_version_find(&which, to)?;
+    match (from, to) {
+        (Some(from), Some(to)) => {
+            let changes = diff_models(&from.model, &to.model);
+            Ok(Response::json(&json!({
+                "result": { "from": from.id, "to": to.id, "changes": changes }
+            })))
+        }
+        _ => Ok(Response::empty_404()),
+    }
+}
+
 pub fn train(
     _request: &Request,
-    _which: String,
+    which: String,
+    context: &Context<impl Store + Send + Sync>,
+) -> Result<Response, Error> {
+    let job = Core::train_job(&context.core, &which);
+    Ok(Response::json(&json!({ "result": job })).with_status_code(202))
+}
+
+pub fn train_status(
+    _request: &Request,
+    which: String,
+    job: Uuid,
+    context: &Context<impl Store>,
+) -> Result<Response, Error> {
+    match context.core.jobs.find(job) {
+        Some(job) if job.part == which => Ok(Response::json(&json!({ "result": job }))),
+        _ => Ok(Response::empty_404()),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinRequest {
+    pub version: Uuid,
+}
+
+pub fn versions(
+    _request: &Request,
+    which: String,
+    context: &Context<impl Store>,
+) -> Result<Response, Error> {
+    let pointer = context.storage.model_pointer_find(&which)?;
+    let versions = context
+        .core
+        .versions(&which)?
+        .into_iter()
+        .map(|version| {
+            json!({
+                "id": version.id,
+                "created": version.created,
+                "examples": version.examples,
+                "performance": version.performance,
+                "parameters": version.parameters,
+                "iterations": version.convergence.iterations,
+                "stop": version.convergence.stop,
+                "active": pointer.active == Some(version.id),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Response::json(
+        &json!({ "result": { "pointer": pointer, "versions": versions } }),
+    ))
+}
+
+pub fn version(
+    _request: &Request,
+    which: String,
+    id: Uuid,
+    context: &Context<impl Store>,
+) -> Result<Response, Error> {
+    match context.storage.model_version_find(&which, id)? {
+        Some(version) => Ok(Response::json(&json!({ "result": version }))),
+        None => Ok(Response::empty_404()),
+    }
+}
+
+pub fn pin(
+    request: &Request,
+    which: String,
+    context: &Context<impl Store>,
+) -> Result<Response, Error> {
+    let pin: PinRequest = rouille::input::json_input(request)?;
+    match context.core.pin_version(&which, pin.version)? {
+        Some(pointer) => Ok(Response::json(&json!({ "result": pointer }))),
+        None => Ok(Response::empty_404()),
+    }
+}
+
+pub fn unpin(
+    _request: &Request,
+    which: String,
+    context: &Context<impl Store>,
+) -> Result<Response, Error> {
+    let pointer = context.core.unpin_version(&which)?;
+    Ok(Response::json(&json!({ "result": pointer })))
+}
+
+pub fn rollback(
+    _request: &Request,
+    which: String,
     context: &Context<impl Store>,
 ) -> Result<Response, Error> {
-    context.core.load_train()?;
-    Ok(Response::empty_204())
+    match context.core.rollback_version(&which)? {
+        Some(pointer) => Ok(Response::json(&json!({ "result": pointer }))),
+        None => Ok(Response::json(
+            &json!({ "description": "no previous version to roll back to" }),
+        )
+        .with_status_code(409)),
+    }
 }
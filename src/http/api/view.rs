@@ -14,6 +14,10 @@ pub struct ViewRequest {
     pub item: Uuid,
     #[serde(alias = "a")]
     pub actid: Option<Uuid>,
+    /// The type of event the item was viewed with, if the view completes an
+    /// activity.  Parts can weigh the examples of each type differently.
+    #[serde(default, alias = "e")]
+    pub event: Option<String>,
 }
 
 pub fn apply_get(request: &Request, context: &Context<impl Store>) -> Result<Response, Error> {
@@ -25,6 +29,9 @@ pub fn apply_get(request: &Request, context: &Context<impl Store>) -> Result<Res
         .get_param("actid")
         .or_else(|| request.get_param("a"));
     let actid = actid.and_then(|i| i.parse::<Uuid>().ok());
+    let event = request
+        .get_param("event")
+        .or_else(|| request.get_param("e"));
     let pair = user.and_then(|u| item.and_then(|i| part.map(|p| (u, i, p))));
 
     match pair {
@@ -34,6 +41,7 @@ pub fn apply_get(request: &Request, context: &Context<impl Store>) -> Result<Res
                 user,
                 item,
                 actid,
+                event,
             };
             apply(request, &view, context)
         }
@@ -96,5 +104,17 @@ fn complete_activity(
     let chosen = [view.item];
     context
         .storage
-        .model_activity_choose(&view.part, activity, &chosen)
+        .model_activity_choose(&view.part, activity, &chosen, view.event.as_deref())?;
+    if context.core.config_for(&view.part).exploration.uses_stats() {
+        context
+            .storage
+            .model_stats_record(&view.part, &[], &chosen)?;
+    }
+    // The choice has already been stored by now, so the view succeeds even
+    // if the model couldn't learn from it; it's still trained on later.
+    if let Err(e) = context.core.learn_online(&view.part, activity) {
+        error!("could not learn online from activity {}: {:?}", activity, e);
+    }
+
+    Ok(())
 }
@@ -8,6 +8,7 @@ use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Instant;
+use uuid::Uuid;
 
 mod api;
 #[cfg(all(test, feature = "lmdb"))]
@@ -23,7 +24,7 @@ pub fn run(config: Config) -> ! {
     start_server(addr, move |request| handle(request, &context))
 }
 
-fn handle(request: &Request, context: &Context<impl Store>) -> Response {
+fn handle(request: &Request, context: &Context<impl Store + Send + Sync>) -> Response {
     debug!("{} {}", request.method(), request.url());
     match handle_request(request, context) {
         Ok(r) => r,
@@ -36,7 +37,10 @@ fn handle(request: &Request, context: &Context<impl Store>) -> Response {
     }
 }
 
-fn handle_request(request: &Request, context: &Context<impl Store>) -> Result<Response, Error> {
+fn handle_request(
+    request: &Request,
+    context: &Context<impl Store + Send + Sync>,
+) -> Result<Response, Error> {
     router!(request,
         (POST)["/api/recommend"] => {  api::recommend::apply(request, &context) },
         (GET)["/api/view"] => { api::view::apply_get(request, &context) },
@@ -45,7 +49,27 @@ fn handle_request(request: &Request, context: &Context<impl Store>) -> Result<Re
         (DELETE)["/api/items"] => { api::items::delete::apply(request, &context) },
         (GET)["/api/items"] => { api::items::show::apply(request, &context) },
         (GET)["/api/model/{name}", name: String] => { api::model::show(request, name, context) },
+        (GET)["/api/model/{name}/inspect", name: String] => {
+            api::model::inspect(request, name, context)
+        },
+        (GET)["/api/model/{name}/diff/{from}/{to}", name: String, from: Uuid, to: Uuid] => {
+            api::model::diff(request, name, from, to, context)
+        },
         (POST)["/api/model/{name}/train", name: String] => { api::model::train(request, name, context) },
+        (GET)["/api/model/{name}/train/{job}", name: String, job: Uuid] => {
+            api::model::train_status(request, name, job, context)
+        },
+        (GET)["/api/model/{name}/versions", name: String] => {
+            api::model::versions(request, name, context)
+        },
+        (GET)["/api/model/{name}/versions/{id}", name: String, id: Uuid] => {
+            api::model::version(request, name, id, context)
+        },
+        (POST)["/api/model/{name}/pin", name: String] => { api::model::pin(request, name, context) },
+        (DELETE)["/api/model/{name}/pin", name: String] => { api::model::unpin(request, name, context) },
+        (POST)["/api/model/{name}/rollback", name: String] => {
+            api::model::rollback(request, name, context)
+        },
         _ => { Ok(Response::empty_404()) })
 }
 
@@ -59,7 +83,8 @@ pub struct Context<T: Store + 'static> {
 impl Context<DefaultStorage> {
     pub fn load(config: Config) -> Context<DefaultStorage> {
         let storage = Arc::new(DefaultStorage::load(&config));
-        let core = Arc::new(Core::of(&storage, &config));
+        let core = Core::of(&storage, &config).expect("could not load recommend config");
+        let core = Arc::new(core);
         Context {
             core,
             storage,
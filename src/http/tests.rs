@@ -1,5 +1,5 @@
 use crate::http::{handle_request, Context};
-use crate::recommend::Core;
+use crate::recommend::{Core, PartConfig};
 use crate::storage::{Item, ItemStore, Store};
 use rouille::{Request, Response, ResponseBody};
 use serde::de::DeserializeOwned;
@@ -9,13 +9,22 @@ use std::sync::Arc;
 use uuid::Uuid;
 
 fn context() -> Context<impl Store> {
+    context_with(Default::default())
+}
+
+fn context_with(default_config: PartConfig) -> Context<impl Store> {
     let storage = crate::storage::mem::tests::TemporaryFileWrap::load();
     let storage = Arc::new(storage);
     let core = Core {
         storage: storage.clone(),
         parameters: Default::default(),
         part_config: Default::default(),
-        default_config: Default::default(),
+        default_config,
+        validation: Default::default(),
+        search: Default::default(),
+        versions: Default::default(),
+        jobs: Default::default(),
+        online: Default::default(),
     };
     Context {
         core: Arc::new(core),
@@ -83,6 +92,7 @@ fn gen_item() -> Item {
 fn it_generates_recommendations() {
     use crate::http::api::view::ViewRequest;
     use crate::recommend::{Request as RecommendRequest, Response as RecommendResponse};
+    use crate::storage::ModelStore;
 
     let context = context();
     let items = (0..20).map(|_| gen_item()).collect::<Vec<_>>();
@@ -96,6 +106,7 @@ fn it_generates_recommendations() {
         user: "me".to_string(),
         item: Default::default(),
         actid: None,
+        event: None,
     };
     for item in items.iter() {
         view.item = item.id;
@@ -107,16 +118,425 @@ fn it_generates_recommendations() {
     let recreq = RecommendRequest {
         part: "default".to_string(),
         user: "me".to_string(),
-        current: items.first().unwrap().id,
+        current: Some(items.first().unwrap().id),
         whitelist: None,
+        exclude: vec![],
+        filter: None,
         count: 5,
+        explain: true,
     };
 
     let request = request("POST", "/api/recommend", Some(&recreq), vec![]);
     let mut response = handle_request(&request, &context).expect("could not perform request");
     assert_eq!(response.status_code, 200);
-    let data = read_all::<RecommendResponse>(&mut response);
+    let data = read_all::<Value>(&mut response);
+    let data = serde_json::from_value::<RecommendResponse>(data["result"].clone()).unwrap();
     assert_eq!(data.items.len(), 5);
-    dbg!(data);
-    assert!(false);
+    let explanations = data.explanations.expect("no explanations");
+    assert_eq!(explanations.len(), 5);
+    for ((id, _), explanation) in data.items.iter().zip(explanations.iter()) {
+        assert_eq!(*id, explanation.item);
+        assert!(!explanation.sources.is_empty());
+    }
+    // Exploration may swap in an item from another source.
+    assert!(explanations
+        .iter()
+        .any(|e| e.sources.contains(&"near".to_string())));
+
+    let activity = context
+        .storage
+        .model_activity_load("default", data.id)
+        .unwrap()
+        .expect("activity not saved");
+    assert_eq!(activity.impressions.len(), 5);
+    for (position, (impression, (_, score))) in activity
+        .impressions
+        .iter()
+        .zip(data.items.iter())
+        .enumerate()
+    {
+        assert_eq!(impression.position, position);
+        assert_eq!(impression.score, *score);
+        assert!(impression.propensity > 0.0 && impression.propensity <= 1.0);
+    }
+}
+
+#[test]
+fn it_trains_models_in_the_background() {
+    use crate::recommend::{JobStatus, TrainJob};
+    use crate::storage::{Activity, BasicExample, Example, ModelStore};
+
+    let context = context();
+    let item = gen_item();
+    let example = Example::new(BasicExample::new(item.id), item);
+    let activity = Activity {
+        id: Uuid::new_v4(),
+        part: "default".to_string(),
+        current: example.clone(),
+        visible: vec![example.clone()],
+        chosen: Some(vec![example.item.id]),
+        event: None,
+        created: 0,
+        impressions: vec![],
+        learned: false,
+    };
+    context
+        .storage
+        .model_activity_save("default", &activity)
+        .unwrap();
+
+    let train_request = request(
+        "POST",
+        "/api/model/default/train",
+        None as Option<&()>,
+        vec![],
+    );
+    let mut response = handle_request(&train_request, &context).expect("could not perform request");
+    assert_eq!(response.status_code, 202);
+    let data = read_all::<Value>(&mut response);
+    let job = serde_json::from_value::<TrainJob>(data["result"].clone()).unwrap();
+    assert_eq!(job.part, "default");
+
+    let url = format!("/api/model/default/train/{}", job.id);
+    let status = (0..100)
+        .map(|_| {
+            let status_request = request("GET", url.clone(), None as Option<&()>, vec![]);
+            let mut response =
+                handle_request(&status_request, &context).expect("could not perform request");
+            assert_eq!(response.status_code, 200);
+            let data = read_all::<Value>(&mut response);
+            let job = serde_json::from_value::<TrainJob>(data["result"].clone()).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            job.status
+        })
+        .find(|status| *status != JobStatus::Queued && *status != JobStatus::Running);
+    // One activity isn't enough to train on, so it's kept for later.
+    assert_eq!(status, Some(JobStatus::Skipped));
+    let kept = context.storage.model_activity_list_part("default").unwrap();
+    assert_eq!(kept.len(), 1);
+    assert_eq!(kept[0].id, activity.id);
+
+    let url = format!("/api/model/other/train/{}", job.id);
+    let status_request = request("GET", url, None as Option<&()>, vec![]);
+    let response = handle_request(&status_request, &context).expect("could not perform request");
+    assert_eq!(response.status_code, 404);
+
+    // Neither is it enough for the periodic training of every part.
+    context.core.load_train().unwrap();
+    let kept = context.storage.model_activity_list_part("default").unwrap();
+    assert_eq!(kept.len(), 1);
+}
+
+#[test]
+fn it_pins_and_rolls_back_model_versions() {
+    use crate::storage::{FeatureList, Model, ModelPointer, ModelStore, ModelVersion};
+
+    let context = context();
+    let version = |weight: f64| {
+        let mut list = FeatureList::default();
+        list.insert("list:near:rank", weight);
+        ModelVersion::new(
+            "default",
+            128,
+            0.5,
+            Default::default(),
+            Model::linear(list),
+            Default::default(),
+        )
+    };
+    let served = || {
+        let model = context.storage.find_model("default").unwrap().unwrap();
+        model.weights.get("list:near:rank").cloned()
+    };
+    let (first, second, third) = (version(1.0), version(2.0), version(3.0));
+
+    assert!(context.core.submit_version(&first, true).unwrap());
+    assert!(context.core.submit_version(&second, true).unwrap());
+    assert_eq!(served(), Some(2.0));
+
+    let list_request = request(
+        "GET",
+        "/api/model/default/versions",
+        None as Option<&()>,
+        vec![],
+    );
+    let mut response = handle_request(&list_request, &context).expect("could not perform request");
+    assert_eq!(response.status_code, 200);
+    let data = read_all::<Value>(&mut response);
+    assert_eq!(data["result"]["versions"].as_array().unwrap().len(), 2);
+    assert_eq!(data["result"]["versions"][1]["active"], Value::Bool(true));
+
+    let rollback_request = request(
+        "POST",
+        "/api/model/default/rollback",
+        None as Option<&()>,
+        vec![],
+    );
+    let response = handle_request(&rollback_request, &context).expect("could not perform request");
+    assert_eq!(response.status_code, 200);
+    assert_eq!(served(), Some(1.0));
+
+    let pin = json!({ "version": second.id });
+    let pin_request = request("POST", "/api/model/default/pin", Some(&pin), vec![]);
+    let mut response = handle_request(&pin_request, &context).expect("could not perform request");
+    assert_eq!(response.status_code, 200);
+    let data = read_all::<Value>(&mut response);
+    let pointer = serde_json::from_value::<ModelPointer>(data["result"].clone()).unwrap();
+    assert!(pointer.pinned);
+    assert_eq!(pointer.active, Some(second.id));
+    assert_eq!(served(), Some(2.0));
+
+    // Pinned versions aren't replaced by newly trained ones.
+    assert!(!context.core.submit_version(&third, true).unwrap());
+    assert_eq!(served(), Some(2.0));
+
+    // Only the most recent versions are kept, along with the pinned one.
+    let retain = context.core.versions.retain;
+    for _ in 0..retain {
+        assert!(!context.core.submit_version(&version(4.0), true).unwrap());
+    }
+    let versions = context.storage.model_version_list("default").unwrap();
+    assert_eq!(versions.len(), retain + 1);
+    assert_eq!(versions[0], second.id);
+    assert!(!versions.contains(&first.id));
+    assert!(!versions.contains(&third.id));
+    let pointer = context.storage.model_pointer_find("default").unwrap();
+    assert!(!pointer.history.contains(&first.id));
+}
+
+#[test]
+fn it_inspects_and_diffs_models() {
+    use crate::storage::{FeatureList, Model, ModelVersion};
+
+    let context = context();
+    let version = |near: f64, top: f64| {
+        let mut list = FeatureList::default();
+        list.insert("list:near:rank", near);
+        list.insert("list:near:score", -3.0);
+        list.insert("list:top:day:rank", top);
+        let mut model = Model::linear(list);
+        model.coverage.insert("list:near:rank".to_owned(), 0.25);
+        ModelVersion::new(
+            "default",
+            128,
+            0.5,
+            Default::default(),
+            model,
+            Default::default(),
+        )
+    };
+    let (first, second) = (version(1.0, 0.5), version(4.0, 0.5));
+    assert!(context.core.submit_version(&first, true).unwrap());
+    assert!(context.core.submit_version(&second, true).unwrap());
+
+    let inspect_request = request(
+        "GET",
+        "/api/model/default/inspect",
+        None as Option<&()>,
+        vec![],
+    );
+    let mut response =
+        handle_request(&inspect_request, &context).expect("could not perform request");
+    assert_eq!(response.status_code, 200);
+    let data = read_all::<Value>(&mut response);
+    let groups = data["result"]["groups"].as_array().unwrap();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0]["prefix"], "list:near");
+    assert_eq!(groups[0]["features"][0]["feature"], "list:near:rank");
+    assert_eq!(groups[0]["features"][0]["coverage"], 0.25);
+    assert_eq!(groups[0]["features"][1]["feature"], "list:near:score");
+    assert_eq!(groups[0]["features"][1]["coverage"], Value::Null);
+    assert_eq!(groups[1]["prefix"], "list:top");
+
+    let path = format!("/api/model/default/diff/{}/{}", first.id, second.id);
+    let diff_request = request("GET", &path, None as Option<&()>, vec![]);
+    let mut response = handle_request(&diff_request, &context).expect("could not perform request");
+    assert_eq!(response.status_code, 200);
+    let data = read_all::<Value>(&mut response);
+    let changes = data["result"]["changes"].as_array().unwrap();
+    assert_eq!(changes.len(), 3);
+    assert_eq!(changes[0]["feature"], "list:near:rank");
+    assert_eq!(changes[0]["change"], 3.0);
+
+    let path = format!("/api/model/default/diff/{}/{}", first.id, Uuid::new_v4());
+    let missing_request = request("GET", &path, None as Option<&()>, vec![]);
+    let response = handle_request(&missing_request, &context).expect("could not perform request");
+    assert_eq!(response.status_code, 404);
+}
+
+#[test]
+fn it_learns_online_from_chosen_items() {
+    use crate::http::api::view::ViewRequest;
+    use crate::recommend::{Request as RecommendRequest, Response as RecommendResponse};
+    use crate::storage::ModelStore;
+
+    let config = PartConfig {
+        online: serde_json::from_value(json!({ "persist-every": 1 })).unwrap(),
+        ..Default::default()
+    };
+    let context = context_with(config);
+    let items = (0..10).map(|_| gen_item()).collect::<Vec<_>>();
+    for item in items.iter() {
+        context.storage.items_insert(item).unwrap();
+    }
+
+    let recreq = RecommendRequest {
+        part: "default".to_string(),
+        user: "me".to_string(),
+        current: Some(items[0].id),
+        whitelist: None,
+        exclude: vec![],
+        filter: None,
+        count: 5,
+        explain: false,
+    };
+    let recommend_request = request("POST", "/api/recommend", Some(&recreq), vec![]);
+    let mut response =
+        handle_request(&recommend_request, &context).expect("could not perform request");
+    let data = read_all::<Value>(&mut response);
+    let data = serde_json::from_value::<RecommendResponse>(data["result"].clone()).unwrap();
+    assert!(context.core.online.find("default").is_none());
+
+    let view = ViewRequest {
+        part: "default".to_string(),
+        user: "me".to_string(),
+        item: data.items[0].0,
+        actid: Some(data.id),
+        event: Some("purchase".to_string()),
+    };
+    let view_request = request("POST", "/api/view", Some(&view), vec![]);
+    let response = handle_request(&view_request, &context).expect("could not perform request");
+    assert_eq!(response.status_code, 204);
+
+    let model = context
+        .core
+        .online
+        .find("default")
+        .expect("no online model");
+    assert!(!model.weights.is_empty());
+    let stored = context.storage.find_model("default").unwrap().unwrap();
+    assert_eq!(stored.weights.len(), model.weights.len());
+
+    // Another item chosen from the same activity isn't learned from again.
+    let view = ViewRequest {
+        item: data.items[1].0,
+        ..view
+    };
+    let view_request = request("POST", "/api/view", Some(&view), vec![]);
+    let response = handle_request(&view_request, &context).expect("could not perform request");
+    assert_eq!(response.status_code, 204);
+    let again = context.core.online.find("default").unwrap();
+    assert!(std::sync::Arc::ptr_eq(&model, &again));
+}
+
+#[test]
+fn it_excludes_items() {
+    use crate::http::api::view::ViewRequest;
+    use crate::recommend::{Request as RecommendRequest, Response as RecommendResponse};
+
+    let config = PartConfig {
+        exclude_history: true,
+        ..Default::default()
+    };
+    let context = context_with(config);
+    let items = (0..20).map(|_| gen_item()).collect::<Vec<_>>();
+    for item in items.iter() {
+        context.storage.items_insert(item).unwrap();
+    }
+
+    for item in items[..5].iter() {
+        let view = ViewRequest {
+            part: "default".to_string(),
+            user: "me".to_string(),
+            item: item.id,
+            actid: None,
+            event: None,
+        };
+        let request = request("POST", "/api/view", Some(&view), vec![]);
+        let response = handle_request(&request, &context).expect("could not perform request");
+        assert_eq!(response.status_code, 204);
+    }
+
+    let recreq = RecommendRequest {
+        part: "default".to_string(),
+        user: "me".to_string(),
+        current: Some(items[10].id),
+        whitelist: None,
+        exclude: items[15..].iter().map(|item| item.id).collect(),
+        filter: None,
+        count: 20,
+        explain: false,
+    };
+    let request = request("POST", "/api/recommend", Some(&recreq), vec![]);
+    let mut response = handle_request(&request, &context).expect("could not perform request");
+    assert_eq!(response.status_code, 200);
+    let data = read_all::<Value>(&mut response);
+    let data = serde_json::from_value::<RecommendResponse>(data["result"].clone()).unwrap();
+    let recommended = data.items.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    assert!(!recommended.is_empty());
+    for item in items[..5].iter().chain(&items[10..11]).chain(&items[15..]) {
+        assert!(!recommended.contains(&item.id));
+    }
+}
+
+#[test]
+fn it_filters_and_refills_candidates() {
+    use crate::http::api::view::ViewRequest;
+    use crate::recommend::{Request as RecommendRequest, Response as RecommendResponse};
+
+    let config = PartConfig {
+        max_candidate_count: 4,
+        ..Default::default()
+    };
+    let context = context_with(config);
+    let items = (0..20)
+        .map(|i| {
+            let mut item = gen_item();
+            let category = if i % 4 == 0 { "shoes" } else { "hats" };
+            item.meta.insert(
+                "category".to_string(),
+                Some(category.to_string()).into_iter().collect(),
+            );
+            item
+        })
+        .collect::<Vec<_>>();
+    for item in items.iter() {
+        context.storage.items_insert(item).unwrap();
+        let view = ViewRequest {
+            part: "default".to_string(),
+            user: "someone".to_string(),
+            item: item.id,
+            actid: None,
+            event: None,
+        };
+        let request = request("POST", "/api/view", Some(&view), vec![]);
+        let response = handle_request(&request, &context).expect("could not perform request");
+        assert_eq!(response.status_code, 204);
+    }
+
+    let recreq = RecommendRequest {
+        part: "default".to_string(),
+        user: "me".to_string(),
+        current: None,
+        whitelist: None,
+        exclude: vec![],
+        filter: serde_json::from_value(json!({
+            "type": "equals", "key": "category", "value": "shoes"
+        }))
+        .unwrap(),
+        count: 4,
+        explain: false,
+    };
+    let request = request("POST", "/api/recommend", Some(&recreq), vec![]);
+    let mut response = handle_request(&request, &context).expect("could not perform request");
+    assert_eq!(response.status_code, 200);
+    let data = read_all::<Value>(&mut response);
+    let data = serde_json::from_value::<RecommendResponse>(data["result"].clone()).unwrap();
+    assert_eq!(data.items.len(), 4);
+    let shoes = items
+        .iter()
+        .step_by(4)
+        .map(|item| item.id)
+        .collect::<Vec<_>>();
+    assert!(data.items.iter().all(|(id, _)| shoes.contains(id)));
 }
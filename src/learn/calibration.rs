@@ -0,0 +1,190 @@
+use std::cmp::Ordering;
+
+use num_traits::Float;
+
+use crate::learn::logistic::sigmoid;
+
+/// A mapping from the predictions of a model to probabilities.  This is fit
+/// after training, on examples the model wasn't trained on, so that the
+/// predictions match how often examples are actually chosen.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Calibration<T> {
+    /// Predictions are used as they are.
+    #[default]
+    Identity,
+    /// Platt scaling: the log-odds of the prediction are scaled by `a`, and
+    /// offset by `b`.
+    Platt { a: T, b: T },
+    /// Isotonic regression: predictions are mapped by a non-decreasing,
+    /// piecewise-linear function through the points, which are ordered by
+    /// prediction.
+    Isotonic { points: Vec<(T, T)> },
+}
+
+/// The number of Newton's method iterations used to fit Platt scaling.
+const PLATT_ITERATIONS: usize = 100;
+
+impl<T: Float + Default + 'static> Calibration<T> {
+    /// Fits Platt scaling to the predictions, given as `(prediction, label)`.
+    /// The labels are smoothed as in Platt's paper, so that a handful of
+    /// examples can't push the probabilities to zero or one.
+    pub fn platt(scored: &[(T, T)]) -> Calibration<T> {
+        let two = T::one() + T::one();
+        let positives = scored
+            .iter()
+            .map(|(_, label)| *label)
+            .fold(T::zero(), T::add);
+        let negatives = T::from(scored.len()).unwrap() - positives;
+        let high = (positives + T::one()) / (positives + two);
+        let low = T::one() / (negatives + two);
+        let ridge = T::from(1e-9).unwrap();
+        let (mut a, mut b) = (T::one(), T::zero());
+
+        for _ in 0..PLATT_ITERATIONS {
+            let (mut ga, mut gb) = (T::zero(), T::zero());
+            let (mut haa, mut hab, mut hbb) = (ridge, T::zero(), ridge);
+            for (prediction, label) in scored {
+                let x = logit(*prediction);
+                let target = if *label > T::zero() { high } else { low };
+                let p = sigmoid(a * x + b);
+                let d = p - target;
+                let w = p * (T::one() - p);
+                ga = ga + d * x;
+                gb = gb + d;
+                haa = haa + w * x * x;
+                hab = hab + w * x;
+                hbb = hbb + w;
+            }
+
+            let det = haa * hbb - hab * hab;
+            if det.abs() <= T::epsilon() {
+                break;
+            }
+            let da = (hbb * ga - hab * gb) / det;
+            let db = (haa * gb - hab * ga) / det;
+
+            // A full Newton step can overshoot, so the step is halved until
+            // it actually lowers the loss.
+            let current = platt_loss(scored, a, b, high, low);
+            let mut step = T::one();
+            while step > ridge {
+                let (na, nb) = (a - step * da, b - step * db);
+                if platt_loss(scored, na, nb, high, low) <= current {
+                    a = na;
+                    b = nb;
+                    break;
+                }
+                step = step / two;
+            }
+
+            if step <= ridge || (da.abs() < ridge && db.abs() < ridge) {
+                break;
+            }
+        }
+
+        Calibration::Platt { a, b }
+    }
+
+    /// Fits isotonic regression to the predictions, given as `(prediction,
+    /// label)`, with the pool adjacent violators algorithm.
+    pub fn isotonic(scored: &[(T, T)]) -> Calibration<T> {
+        let mut sorted = scored.to_vec();
+        sorted.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+        // Each block is the sum of its predictions, the sum of its labels,
+        // and its size.
+        let mut blocks: Vec<(T, T, T)> = Vec::with_capacity(sorted.len());
+        for (prediction, label) in sorted {
+            blocks.push((prediction, label, T::one()));
+            while blocks.len() > 1 {
+                let (x, y, n) = blocks[blocks.len() - 1];
+                let (px, py, pn) = blocks[blocks.len() - 2];
+                if py / pn < y / n {
+                    break;
+                }
+                blocks.pop();
+                let last = blocks.len() - 1;
+                blocks[last] = (px + x, py + y, pn + n);
+            }
+        }
+
+        let points = blocks.into_iter().map(|(x, y, n)| (x / n, y / n)).collect();
+        Calibration::Isotonic { points }
+    }
+
+    pub fn apply(&self, prediction: T) -> T {
+        match self {
+            Calibration::Identity => prediction,
+            Calibration::Platt { a, b } => sigmoid(*a * logit(prediction) + *b),
+            Calibration::Isotonic { points } => interpolate(points, prediction),
+        }
+    }
+}
+
+/// The cross-entropy of Platt scaling with the given parameters, against the
+/// smoothed labels.
+fn platt_loss<T: Float + Default + 'static>(scored: &[(T, T)], a: T, b: T, high: T, low: T) -> T {
+    scored
+        .iter()
+        .map(|(prediction, label)| {
+            let target = if *label > T::zero() { high } else { low };
+            let z = a * logit(*prediction) + b;
+            // log(1 + e^z) - target * z, computed so that it can't overflow.
+            let softplus = z.max(T::zero()) + (-z.abs()).exp().ln_1p();
+            softplus - target * z
+        })
+        .fold(T::zero(), T::add)
+}
+
+/// The log-odds of the probability, which is clamped away from zero and one
+/// so that they're finite.
+fn logit<T: Float>(probability: T) -> T {
+    let p = probability.max(T::epsilon()).min(T::one() - T::epsilon());
+    (p / (T::one() - p)).ln()
+}
+
+fn interpolate<T: Float>(points: &[(T, T)], x: T) -> T {
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return x,
+    };
+    if x <= first.0 {
+        return first.1;
+    } else if x >= last.0 {
+        return last.1;
+    }
+
+    let idx = points.iter().position(|(px, _)| *px > x).unwrap_or(0);
+    let ((x1, y1), (x2, y2)) = (points[idx - 1], points[idx]);
+    y1 + (y2 - y1) * (x - x1) / (x2 - x1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_calibrates_predictions() {
+        // The model is overconfident: examples it predicts at 0.9 are only
+        // chosen half of the time, and ones at 0.1 are never chosen.
+        let scored = (0..100)
+            .map(|i| match i % 4 {
+                0 => (0.9, 1.0),
+                1 => (0.9, 0.0),
+                _ => (0.1, 0.0),
+            })
+            .collect::<Vec<_>>();
+
+        let platt = Calibration::platt(&scored);
+        let high = platt.apply(0.9);
+        assert!((high - 0.5).abs() < 0.05, "{}", high);
+        assert!(platt.apply(0.1) < 0.05);
+
+        let isotonic = Calibration::isotonic(&scored);
+        assert!((isotonic.apply(0.9) - 0.5).abs() < 1e-9);
+        assert!(isotonic.apply(0.1).abs() < 1e-9);
+        assert!((isotonic.apply(0.5) - 0.25).abs() < 1e-9);
+        assert_eq!(Calibration::Identity.apply(0.3), 0.3);
+    }
+}
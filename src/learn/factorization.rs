@@ -0,0 +1,343 @@
+use num_traits::Float;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::learn::logistic::sigmoid;
+use crate::learn::{Algorithm, Convergence, Stop};
+
+use super::{SparseVector, Vector};
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Parameters<T: Float + Default + 'static> {
+    learning_rate: T,
+    iteration_cap: usize,
+    /// The number of latent factors each feature has.
+    factors: usize,
+    /// The L2 regularization of the linear weights, and of the factors.
+    regularization: (T, T),
+    /// The latent factors are initialized to a random value between the
+    /// negative and positive of this.
+    deviation: T,
+    /// Training stops once an iteration improves the loss by less than this.
+    tolerance: T,
+    seed: u64,
+}
+
+impl<T: Float + Default + 'static> Default for Parameters<T> {
+    fn default() -> Parameters<T> {
+        Parameters {
+            learning_rate: T::from(0.05).unwrap(),
+            iteration_cap: 100,
+            factors: 8,
+            regularization: (T::zero(), T::from(0.01).unwrap()),
+            deviation: T::from(0.01).unwrap(),
+            tolerance: T::from(1e-6).unwrap(),
+            seed: 0,
+        }
+    }
+}
+
+impl<T: Float + Default + 'static> Parameters<T> {
+    pub fn learning_rate(self, learning_rate: T) -> Self {
+        Parameters {
+            learning_rate,
+            ..self
+        }
+    }
+
+    pub fn iteration_cap(self, iteration_cap: usize) -> Self {
+        Parameters {
+            iteration_cap,
+            ..self
+        }
+    }
+
+    pub fn factors(self, factors: usize) -> Self {
+        Parameters { factors, ..self }
+    }
+
+    pub fn regularization(self, linear: T, factors: T) -> Self {
+        Parameters {
+            regularization: (linear, factors),
+            ..self
+        }
+    }
+
+    pub fn seed(self, seed: u64) -> Self {
+        Parameters { seed, ..self }
+    }
+
+    pub fn build(self) -> FactorizationMachine<T> {
+        self.build_with(Vector::empty(), vec![])
+    }
+
+    /// Builds the machine with existing weights and factors.  The factors
+    /// are given per feature, in the same order as the weights; any factors
+    /// that are missing (or too short) are randomly initialized when the
+    /// machine is trained, and treated as zero until then.
+    pub fn build_with(
+        self,
+        weights: Vector<T>,
+        factors: Vec<Vector<T>>,
+    ) -> FactorizationMachine<T> {
+        FactorizationMachine {
+            parameters: self,
+            loss: T::infinity(),
+            weights,
+            factors,
+        }
+    }
+}
+
+/// A second-order factorization machine, used for binary classification.
+/// On top of a linear weight per feature, each feature has a vector of
+/// latent factors, and every pair of features interacts with a weight of
+/// the dot product of their factors.
+#[derive(Debug, Clone)]
+pub struct FactorizationMachine<T: Float + Default + 'static> {
+    parameters: Parameters<T>,
+    loss: T,
+    weights: Vector<T>,
+    factors: Vec<Vector<T>>,
+}
+
+impl<T: Float + Default + 'static> FactorizationMachine<T> {
+    pub fn weights(&self) -> &Vector<T> {
+        &self.weights
+    }
+
+    pub fn factors(&self) -> &[Vector<T>] {
+        &self.factors
+    }
+
+    pub fn loss(&self) -> T {
+        self.loss
+    }
+
+    pub fn predict_one(&self, example: &SparseVector<T>) -> T {
+        sigmoid(self.raw(example).0)
+    }
+
+    /// Calculates the raw (pre-sigmoid) output of the machine for the
+    /// example, along with the sum of each factor over the features (which is
+    /// needed to calculate the gradient).
+    fn raw(&self, example: &SparseVector<T>) -> (T, Vec<T>) {
+        let linear = example.dot(&self.weights);
+        let mut sums = vec![T::zero(); self.parameters.factors];
+        let mut squares = T::zero();
+
+        for (idx, value) in example.iter() {
+            let factors = match self.factors.get(idx) {
+                Some(factors) => factors,
+                None => continue,
+            };
+            for (sum, factor) in sums.iter_mut().zip(factors.iter()) {
+                *sum = *sum + *factor * value;
+                squares = squares + (*factor * value).powi(2);
+            }
+        }
+
+        let pairwise = sums.iter().map(|v| v.powi(2)).fold(T::zero(), T::add) - squares;
+        (linear + pairwise / (T::one() + T::one()), sums)
+    }
+
+    /// Makes sure every feature the examples use has a full set of factors,
+    /// randomly initializing any that are missing.
+    fn initialize(&mut self, examples: &[(SparseVector<T>, T, T)]) {
+        let features = examples
+            .iter()
+            .map(|(e, _, _)| e.dimension())
+            .max()
+            .unwrap_or(0);
+        let count = self.parameters.factors;
+        let deviation = self.parameters.deviation.to_f64().unwrap_or_default();
+        let mut rng = StdRng::seed_from_u64(self.parameters.seed);
+
+        if self.factors.len() < features {
+            self.factors.resize_with(features, Vector::empty);
+        }
+
+        for factors in self.factors.iter_mut() {
+            while factors.len() < count {
+                let value = if deviation > 0.0 {
+                    rng.gen_range(-deviation, deviation)
+                } else {
+                    0.0
+                };
+                factors.push(T::from(value).unwrap_or_default());
+            }
+            factors.truncate(count);
+        }
+
+        if features > 0 {
+            self.weights.resize_to(features - 1);
+        }
+    }
+
+    fn compute_loss(&self, examples: &[(SparseVector<T>, T, T)]) -> T {
+        let epsilon = T::epsilon();
+        let (sum, total) = examples
+            .iter()
+            .map(|(example, target, weight)| {
+                let prediction = self
+                    .predict_one(example)
+                    .max(epsilon)
+                    .min(T::one() - epsilon);
+                let loss = if target.is_zero() {
+                    prediction.neg().ln_1p()
+                } else {
+                    prediction.ln()
+                };
+                (*weight * loss, *weight)
+            })
+            .fold((T::zero(), T::zero()), |(s, t), (l, w)| (s + l, t + w));
+        sum.neg() / total.max(T::min_positive_value())
+    }
+}
+
+impl<T: Float + Default + 'static> Algorithm<T> for FactorizationMachine<T> {
+    /// Performs a single pass of stochastic gradient descent over all of the
+    /// examples.
+    fn fit(&mut self, examples: &[(SparseVector<T>, T, T)]) {
+        let rate = self.parameters.learning_rate;
+        let (l2_linear, l2_factors) = self.parameters.regularization;
+
+        for (example, target, weight) in examples {
+            let (raw, sums) = self.raw(example);
+            let delta = (sigmoid(raw) - *target) * *weight;
+
+            for (idx, value) in example.iter() {
+                let weight = self.weights[idx];
+                self.weights[idx] = weight - rate * (delta * value + l2_linear * weight);
+
+                for (factor, sum) in self.factors[idx].iter_mut().zip(sums.iter()) {
+                    let gradient = value * (*sum - *factor * value);
+                    *factor = *factor - rate * (delta * gradient + l2_factors * *factor);
+                }
+            }
+        }
+
+        self.loss = self.compute_loss(examples);
+    }
+
+    fn train_with_validation(
+        &mut self,
+        examples: &[(SparseVector<T>, T, T)],
+        validation: &[(SparseVector<T>, T, T)],
+    ) -> Convergence<T> {
+        self.initialize(examples);
+        self.loss = self.compute_loss(examples);
+        let mut convergence = Convergence {
+            stop: Stop::IterationCap,
+            ..Default::default()
+        };
+
+        for _ in 0..self.parameters.iteration_cap {
+            let previous = self.loss;
+            self.fit(examples);
+            convergence.iterations += 1;
+            convergence.losses.push(self.loss);
+            if !validation.is_empty() {
+                let validation_loss = self.compute_loss(validation);
+                convergence.validation_losses.push(validation_loss);
+            }
+
+            if (previous - self.loss).abs() < self.parameters.tolerance {
+                convergence.stop = Stop::Converged;
+                break;
+            }
+        }
+
+        convergence
+    }
+
+    fn predict_iter<'o>(
+        &'o self,
+        iter: Box<dyn Iterator<Item = &'o SparseVector<T>> + 'o>,
+    ) -> Box<dyn Iterator<Item = T> + 'o> {
+        Box::new(iter.map(move |example| self.predict_one(example)))
+    }
+
+    fn predict_slice(&self, examples: &[SparseVector<T>]) -> Vec<T> {
+        examples.iter().map(|e| self.predict_one(e)).collect()
+    }
+}
+
+/// Scores a single example, given as an iterator of the feature values with
+/// their weights, and an iterator of the feature values with their factors.
+/// This is the counterpart to [`crate::learn::logistic::predict_iter`].
+pub fn predict_iter<'f, L, F>(linear: L, factors: F) -> f64
+where
+    L: Iterator<Item = (f64, f64)>,
+    F: Iterator<Item = (f64, &'f [f64])>,
+{
+    let linear = linear.map(|(a, b)| a * b).fold(0.0, std::ops::Add::add);
+    let mut sums = Vec::<f64>::new();
+    let mut squares = 0.0;
+
+    for (value, factors) in factors {
+        if sums.len() < factors.len() {
+            sums.resize(factors.len(), 0.0);
+        }
+        for (sum, factor) in sums.iter_mut().zip(factors.iter()) {
+            *sum += factor * value;
+            squares += (factor * value).powi(2);
+        }
+    }
+
+    let pairwise = sums.iter().map(|v| v.powi(2)).sum::<f64>() - squares;
+    sigmoid(linear + pairwise / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example(a: f64, b: f64, target: f64) -> (SparseVector<f64>, f64, f64) {
+        (vec![a, b].into(), target, 1.0)
+    }
+
+    #[test]
+    fn it_learns_interactions() {
+        // The target is only positive when both features are present, which
+        // a linear model without a bias term can't represent.
+        let examples = (0..16)
+            .flat_map(|_| {
+                vec![
+                    example(1.0, 0.0, 0.0),
+                    example(0.0, 1.0, 0.0),
+                    example(1.0, 1.0, 1.0),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let mut fm = Parameters::default()
+            .learning_rate(0.1)
+            .iteration_cap(2_000)
+            .factors(2)
+            .regularization(0.0, 0.0)
+            .build();
+        fm.train(&examples);
+
+        let both = fm.predict_one(&vec![1.0, 1.0].into());
+        let one = fm.predict_one(&vec![1.0, 0.0].into());
+        assert!(both > 0.5, "both={}", both);
+        assert!(one < 0.5, "one={}", one);
+    }
+
+    #[test]
+    fn it_predicts_like_the_serving_path() {
+        let mut fm = Parameters::default().factors(2).seed(7).build();
+        fm.train(&[example(1.0, 2.0, 1.0), example(2.0, 0.5, 0.0)]);
+
+        let input = vec![0.5, 1.5];
+        let expected = fm.predict_one(&input.clone().into());
+        let linear = input.iter().cloned().zip(fm.weights().iter().cloned());
+        let factors = input
+            .iter()
+            .cloned()
+            .zip(fm.factors().iter().map(|f| &f[..]));
+        let actual = predict_iter(linear, factors);
+        assert!((expected - actual).abs() < 1e-12);
+    }
+}
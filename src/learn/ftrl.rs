@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use num_traits::Float;
+
+use crate::learn::logistic::sigmoid;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Parameters<T: Float + Default + 'static> {
+    alpha: T,
+    beta: T,
+    l1: T,
+    l2: T,
+}
+
+impl<T: Float + Default + 'static> Default for Parameters<T> {
+    fn default() -> Parameters<T> {
+        Parameters {
+            alpha: T::from(0.1).unwrap(),
+            beta: T::one(),
+            l1: T::zero(),
+            l2: T::zero(),
+        }
+    }
+}
+
+impl<T: Float + Default + 'static> Parameters<T> {
+    pub fn alpha(self, alpha: T) -> Self {
+        Parameters { alpha, ..self }
+    }
+
+    pub fn beta(self, beta: T) -> Self {
+        Parameters { beta, ..self }
+    }
+
+    pub fn l1_regularization(self, l1: T) -> Self {
+        Parameters { l1, ..self }
+    }
+
+    pub fn l2_regularization(self, l2: T) -> Self {
+        Parameters { l2, ..self }
+    }
+
+    pub fn build(self) -> Ftrl<T> {
+        Ftrl {
+            parameters: self,
+            state: HashMap::new(),
+        }
+    }
+
+    /// Builds the learner so that it starts out with the given weights.
+    pub fn build_with_weights<'w>(self, weights: impl Iterator<Item = (&'w str, T)>) -> Ftrl<T> {
+        let mut ftrl = self.build();
+        for (key, weight) in weights {
+            ftrl.state
+                .insert(key.to_owned(), (self.initial_z(weight), T::zero()));
+        }
+        ftrl
+    }
+
+    /// The `z` value that a feature needs to start with in order to have the
+    /// given weight, before anything has been learned.  This is the inverse
+    /// of [`Parameters::weight`] when `n` is zero.
+    pub(crate) fn initial_z(&self, weight: T) -> T {
+        if weight.is_zero() {
+            T::zero()
+        } else {
+            -weight * (self.beta / self.alpha + self.l2) - weight.signum() * self.l1
+        }
+    }
+
+    /// Calculates the weight of a feature from its `z` and `n` values.
+    pub(crate) fn weight(&self, z: T, n: T) -> T {
+        if z.abs() <= self.l1 {
+            T::zero()
+        } else {
+            -(z - z.signum() * self.l1) / ((self.beta + n.sqrt()) / self.alpha + self.l2)
+        }
+    }
+
+    /// Updates the `z` and `n` values of a feature with its current weight
+    /// and the gradient of the loss for it.
+    pub(crate) fn accumulate(&self, z: &mut T, n: &mut T, weight: T, gradient: T) {
+        let sigma = ((*n + gradient.powi(2)).sqrt() - n.sqrt()) / self.alpha;
+        *z = *z + gradient - sigma * weight;
+        *n = *n + gradient.powi(2);
+    }
+}
+
+/// An online logistic regression learner, using the FTRL-Proximal algorithm.
+/// Unlike [`crate::learn::logistic::LogisticRegression`], this learns from a
+/// single example at a time, and each feature gets its own learning rate
+/// (based on how much it has been seen), so it works well for updating a
+/// model as new examples come in.  Features are identified by name.
+#[derive(Debug, Clone)]
+pub struct Ftrl<T: Float + Default + 'static> {
+    parameters: Parameters<T>,
+    /// The `z` and `n` values of each feature.
+    state: HashMap<String, (T, T)>,
+}
+
+impl<T: Float + Default + 'static> Ftrl<T> {
+    /// The current weight of the feature.
+    pub fn weight(&self, key: &str) -> T {
+        match self.state.get(key) {
+            Some((z, n)) => self.parameters.weight(*z, *n),
+            None => T::zero(),
+        }
+    }
+
+    pub fn predict(&self, example: &[(&str, T)]) -> T {
+        let dot = example
+            .iter()
+            .map(|(key, value)| self.weight(key) * *value)
+            .fold(T::zero(), T::add);
+        sigmoid(dot)
+    }
+
+    /// Learns from a single example with the given weight, returning the
+    /// prediction that was made for it before learning.
+    pub fn update(&mut self, example: &[(&str, T)], target: T, weight: T) -> T {
+        let prediction = self.predict(example);
+        let delta = (prediction - target) * weight;
+
+        for (key, value) in example {
+            if value.is_zero() {
+                continue;
+            }
+
+            let weight = self.weight(key);
+            let parameters = self.parameters;
+            let (z, n) = self
+                .state
+                .entry((*key).to_owned())
+                .or_insert((T::zero(), T::zero()));
+            parameters.accumulate(z, n, weight, delta * *value);
+        }
+
+        prediction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_keeps_initial_weights() {
+        let parameters = Parameters::default().l1_regularization(0.5);
+        let ftrl = parameters.build_with_weights(vec![("a", 2.0), ("b", -1.0)].into_iter());
+        assert!((ftrl.weight("a") - 2.0).abs() < 1e-12);
+        assert!((ftrl.weight("b") + 1.0).abs() < 1e-12);
+        assert_eq!(ftrl.weight("c"), 0.0);
+    }
+
+    #[test]
+    fn it_learns_from_each_example() {
+        let mut ftrl = Parameters::default().alpha(0.5).build();
+        for _ in 0..50 {
+            ftrl.update(&[("good", 1.0)], 1.0, 1.0);
+            ftrl.update(&[("bad", 1.0)], 0.0, 1.0);
+        }
+        assert!(ftrl.predict(&[("good", 1.0)]) > 0.8);
+        assert!(ftrl.predict(&[("bad", 1.0)]) < 0.2);
+    }
+}
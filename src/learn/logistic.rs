@@ -2,9 +2,10 @@ use std::mem::swap;
 
 use num_traits::Float;
 
-use crate::learn::Algorithm;
+use crate::learn::optimizer::{Optimizer, OptimizerState};
+use crate::learn::{Algorithm, Convergence, Stop};
 
-use super::Vector;
+use super::{SparseVector, Vector};
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Parameters<T: Float + Default + 'static> {
@@ -12,6 +13,21 @@ pub struct Parameters<T: Float + Default + 'static> {
     gradient_cap: T,
     iteration_cap: usize,
     regularization: Option<(T, T)>,
+    #[serde(default)]
+    optimizer: Optimizer<T>,
+    /// The number of examples in each mini-batch.  This is only used by
+    /// optimizers that don't step over all of the examples at once.
+    #[serde(default = "default_batch_size")]
+    batch_size: usize,
+    /// If given, training stops once the loss on the validation examples
+    /// hasn't improved for this many iterations, and the weights go back to
+    /// the ones with the lowest validation loss.
+    #[serde(default)]
+    pub(crate) patience: Option<usize>,
+}
+
+fn default_batch_size() -> usize {
+    32
 }
 
 impl<T: Float + Default + 'static> Default for Parameters<T> {
@@ -21,6 +37,9 @@ impl<T: Float + Default + 'static> Default for Parameters<T> {
             gradient_cap: T::one(),
             iteration_cap: 10_000,
             regularization: None,
+            optimizer: Optimizer::default(),
+            batch_size: default_batch_size(),
+            patience: None,
         }
     }
 }
@@ -71,6 +90,21 @@ impl<T: Float + Default + 'static> Parameters<T> {
         }
     }
 
+    pub fn optimizer(self, optimizer: Optimizer<T>) -> Self {
+        Parameters { optimizer, ..self }
+    }
+
+    pub fn batch_size(self, batch_size: usize) -> Self {
+        Parameters { batch_size, ..self }
+    }
+
+    pub fn patience(self, patience: usize) -> Self {
+        Parameters {
+            patience: Some(patience),
+            ..self
+        }
+    }
+
     pub fn build(self) -> LogisticRegression<T> {
         let learning_rate = Some(self.learning_rate);
         LogisticRegression {
@@ -80,6 +114,7 @@ impl<T: Float + Default + 'static> Parameters<T> {
             previous: None,
             weights: Vector::empty(),
             gradients: Vector::empty(),
+            state: OptimizerState::default(),
         }
     }
 
@@ -93,6 +128,7 @@ impl<T: Float + Default + 'static> Parameters<T> {
             previous: None,
             weights,
             gradients: Vector::empty(),
+            state: OptimizerState::default(),
         }
     }
 }
@@ -105,6 +141,7 @@ pub struct LogisticRegression<T: Float + Default + 'static> {
     previous: Option<(Vector<T>, Vector<T>)>,
     weights: Vector<T>,
     pub(crate) gradients: Vector<T>,
+    state: OptimizerState<T>,
 }
 
 impl<T: Float + Default + 'static> LogisticRegression<T> {
@@ -118,11 +155,31 @@ impl<T: Float + Default + 'static> LogisticRegression<T> {
 
     pub fn predict<'o>(
         &'o self,
-        examples: impl Iterator<Item = &'o Vector<T>> + 'o,
+        examples: impl Iterator<Item = &'o SparseVector<T>> + 'o,
     ) -> impl Iterator<Item = T> + 'o {
         predict(&self.weights, examples)
     }
 
+    /// Performs a single pass over all of the examples in mini-batches, for
+    /// optimizers that aren't full-batch.
+    fn fit_batches(&mut self, examples: &[(SparseVector<T>, T, T)]) {
+        let regularization = self.parameters.regularization;
+        let learning_rate = self.parameters.learning_rate;
+
+        for batch in examples.chunks(self.parameters.batch_size.max(1)) {
+            let gradient = loss_gradient(batch, &self.weights, regularization);
+            self.parameters.optimizer.step(
+                &mut self.state,
+                &mut self.weights,
+                &gradient,
+                learning_rate,
+            );
+        }
+
+        self.gradients = loss_gradient(examples, &self.weights, regularization);
+        self.loss = loss(examples, &self.weights, regularization);
+    }
+
     fn adjust_learning_rate(&mut self) {
         match self.previous.as_ref() {
             None => {
@@ -152,62 +209,107 @@ impl<T: Float + Default + 'static> LogisticRegression<T> {
 }
 
 impl<T: Float + Default + 'static> Algorithm<T> for LogisticRegression<T> {
-    fn fit(&mut self, examples: &[(Vector<T>, T)]) {
-        match self.learning_rate {
-            None => {}
-            Some(learn) => {
-                let weights = new_weight_step(&self.weights, &self.gradients, learn);
-                let new_loss = loss(examples, &weights, self.parameters.regularization);
-                let new_learn = Some(learn / (T::one() + T::one())).filter(|v| *v > T::zero());
-
-                if new_loss > self.loss {
-                    self.learning_rate = new_learn;
-                    self.fit(examples);
-                } else {
-                    let mut new_weights = weights;
-                    let mut new_gradients =
-                        loss_gradient(examples, &new_weights, self.parameters.regularization);
-                    swap(&mut self.weights, &mut new_weights);
-                    swap(&mut self.gradients, &mut new_gradients);
-                    self.previous = Some((new_weights, new_gradients));
-                    self.loss = loss(examples, &self.weights, self.parameters.regularization);
-                }
+    fn fit(&mut self, examples: &[(SparseVector<T>, T, T)]) {
+        if !self.parameters.optimizer.is_batch() {
+            return self.fit_batches(examples);
+        }
+
+        // Halve the learning rate until a step is found that doesn't
+        // increase the loss, or the learning rate runs out.
+        while let Some(learn) = self.learning_rate {
+            let weights = new_weight_step(&self.weights, &self.gradients, learn);
+            let new_loss = loss(examples, &weights, self.parameters.regularization);
+
+            if new_loss > self.loss {
+                self.learning_rate = Some(learn / (T::one() + T::one())).filter(|v| *v > T::zero());
+                continue;
             }
+
+            let mut new_weights = weights;
+            let mut new_gradients =
+                loss_gradient(examples, &new_weights, self.parameters.regularization);
+            swap(&mut self.weights, &mut new_weights);
+            swap(&mut self.gradients, &mut new_gradients);
+            self.previous = Some((new_weights, new_gradients));
+            self.loss = new_loss;
+            break;
         }
     }
 
-    fn train(&mut self, examples: &[(Vector<T>, T)]) {
-        self.gradients = loss_gradient(examples, &self.weights, self.parameters.regularization);
-        self.loss = loss(examples, &self.weights, self.parameters.regularization);
-        let mut iterations = 0usize;
+    fn train_with_validation(
+        &mut self,
+        examples: &[(SparseVector<T>, T, T)],
+        validation: &[(SparseVector<T>, T, T)],
+    ) -> Convergence<T> {
+        let regularization = self.parameters.regularization;
+        self.gradients = loss_gradient(examples, &self.weights, regularization);
+        self.loss = loss(examples, &self.weights, regularization);
+        let mut convergence = Convergence::default();
+        let mut best: Option<(T, Vector<T>)> = None;
+        let mut stale = 0usize;
+
+        convergence.stop = loop {
+            if self.gradients.magnitude() <= self.parameters.gradient_cap {
+                break Stop::Converged;
+            } else if convergence.iterations >= self.parameters.iteration_cap {
+                break Stop::IterationCap;
+            } else if self.learning_rate.is_none() {
+                break Stop::StepExhausted;
+            }
 
-        while self.gradients.magnitude() > self.parameters.gradient_cap
-            && iterations < self.parameters.iteration_cap
-            && self.learning_rate.is_some()
-        {
-            self.adjust_learning_rate();
+            if self.parameters.optimizer.is_batch() {
+                self.adjust_learning_rate();
+            }
             self.fit(examples);
-            iterations += 1;
+            convergence.iterations += 1;
+            convergence.losses.push(self.loss);
+
+            if validation.is_empty() {
+                continue;
+            }
+
+            let validation_loss = loss(validation, &self.weights, None);
+            convergence.validation_losses.push(validation_loss);
+            match best.as_ref() {
+                Some((lowest, _)) if validation_loss >= *lowest => stale += 1,
+                _ => {
+                    best = Some((validation_loss, self.weights.clone()));
+                    stale = 0;
+                }
+            }
+
+            match self.parameters.patience {
+                Some(patience) if stale >= patience => break Stop::EarlyStopped,
+                _ => {}
+            }
+        };
+
+        if let (Stop::EarlyStopped, Some((_, weights))) = (convergence.stop, best) {
+            self.weights = weights;
+            self.gradients = loss_gradient(examples, &self.weights, regularization);
+            self.loss = loss(examples, &self.weights, regularization);
         }
+
+        convergence
     }
 
     fn predict_iter<'o>(
         &'o self,
-        iter: Box<dyn Iterator<Item = &'o Vector<T>> + 'o>,
+        iter: Box<dyn Iterator<Item = &'o SparseVector<T>> + 'o>,
     ) -> Box<dyn Iterator<Item = T> + 'o> {
         Box::new(predict(self.weights(), iter))
     }
 
-    fn predict_slice(&self, examples: &[Vector<T>]) -> Vec<T> {
+    fn predict_slice(&self, examples: &[SparseVector<T>]) -> Vec<T> {
         predict(self.weights(), examples.iter()).collect()
     }
 }
 
-pub fn predict<'o, T: Float + Default + 'static, E: Iterator<Item = &'o Vector<T>> + 'o>(
+pub fn predict<'o, T: Float + Default + 'static, E: Iterator<Item = &'o SparseVector<T>> + 'o>(
     weights: &'o Vector<T>,
     examples: E,
 ) -> impl Iterator<Item = T> + 'o {
-    examples.map(move |example| sigmoid(example.dot(&weights)))
+    examples.map(move |example| sigmoid(example.dot(weights)))
 }
 
 pub fn predict_iter<T: Float + Default + 'static, E: Iterator<Item = (f64, f64)>>(e: E) -> f64 {
@@ -234,25 +336,28 @@ fn new_weight_step<'c, T: Float + Default>(
 /// logistic models, a number between 0 and 1); if the actual value is true,
 /// then the loss is `-ln(pred)`, where `pred` is the prediction.  These are
 /// all summed together to calculate the total loss on all of the examples.
+/// The loss of each example is multiplied by its weight, and the total is
+/// divided by the sum of the weights, rather than the number of examples.
 fn loss<T: Float + Default + 'static>(
-    examples: &[(Vector<T>, T)],
+    examples: &[(SparseVector<T>, T, T)],
     weights: &Vector<T>,
     regularization: Option<(T, T)>,
 ) -> T {
     let sum = examples
         .iter()
-        .map(|(example, target)| {
+        .map(|(example, target, weight)| {
             let prediction = sigmoid(example.dot(weights));
-            if target.is_zero() {
+            let loss = if target.is_zero() {
                 // ln_1p = ln(1+n), and we need ln(1-n), so we negate n, before
                 // calling ln_1p.
                 prediction.neg().ln_1p()
             } else {
                 prediction.ln()
-            }
+            };
+            *weight * loss
         })
         .fold(T::zero(), T::add);
-    let base = (T::one().neg() / T::from(examples.len()).unwrap()) * sum;
+    let base = (T::one().neg() / total_weight(examples)) * sum;
     let reg = match regularization {
         None => T::zero(),
         Some((l1, l2)) => {
@@ -272,18 +377,23 @@ fn loss<T: Float + Default + 'static>(
 /// and outputting the result.  This **should not** take the place of weights -
 /// this is only one step in the process of gradient descent.
 fn loss_gradient<'l, T: Float + Default + 'static>(
-    examples: &'l [(Vector<T>, T)],
+    examples: &'l [(SparseVector<T>, T, T)],
     weights: &Vector<T>,
     regularization: Option<(T, T)>,
 ) -> Vector<T> {
-    let examples_len = T::from(examples.len()).unwrap();
+    let total = total_weight(examples);
     let mut list = Vector::empty();
+    if !weights.is_empty() {
+        list.resize_to(weights.len() - 1);
+    }
 
-    for (example, target) in examples {
-        let delta = sigmoid(example.dot(weights)) - *target;
-        list.resize_to(example.len() - 1);
-        for (el, v) in list.iter_mut().zip(example.iter()) {
-            *el = *el + (delta * *v) / examples_len;
+    // Only the features that are present in an example contribute to the
+    // gradient, so we only need to visit those.
+    for (example, target, weight) in examples {
+        let delta = (sigmoid(example.dot(weights)) - *target) * *weight;
+        list.resize_to(example.dimension().max(1) - 1);
+        for (idx, v) in example.iter() {
+            list[idx] = list[idx] + (delta * v) / total;
         }
     }
 
@@ -302,6 +412,16 @@ fn loss_gradient<'l, T: Float + Default + 'static>(
     list
 }
 
+/// The sum of the weights of the examples.  If they're all zero, this is the
+/// smallest positive value instead, so that it can be divided by.
+fn total_weight<T: Float + Default + 'static>(examples: &[(SparseVector<T>, T, T)]) -> T {
+    examples
+        .iter()
+        .map(|(_, _, weight)| *weight)
+        .fold(T::zero(), T::add)
+        .max(T::min_positive_value())
+}
+
 // S(x) = 1/(1+e^(-x))
 
 /// Calculates the sigmoid function against the input.  This is critical to the
@@ -310,3 +430,47 @@ fn loss_gradient<'l, T: Float + Default + 'static>(
 pub fn sigmoid<T: Float>(input: T) -> T {
     T::one() / (T::one() + input.neg().exp())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_weighs_examples() {
+        // The same example is chosen once and not chosen once, but being
+        // chosen is weighted three times as much.
+        let examples = vec![(vec![1.0].into(), 1.0, 3.0), (vec![1.0].into(), 0.0, 1.0)];
+        let mut lr = Parameters::default().gradient_cap(1e-6).build();
+        lr.train(&examples);
+        let prediction = lr.predict_slice(&[vec![1.0].into()])[0];
+        assert!((prediction - 0.75).abs() < 1e-3, "{}", prediction);
+    }
+
+    #[test]
+    fn it_stops_early() {
+        // The validation examples disagree with the training examples, so
+        // the validation loss only gets worse.
+        let training = vec![(vec![1.0].into(), 1.0, 1.0)];
+        let validation = vec![(vec![1.0].into(), 0.0, 1.0)];
+        let mut lr = Parameters::default().gradient_cap(1e-9).patience(3).build();
+        let convergence = lr.train_with_validation(&training, &validation);
+
+        assert_eq!(convergence.stop, Stop::EarlyStopped);
+        assert_eq!(convergence.iterations, 4);
+        assert_eq!(convergence.losses.len(), 4);
+        assert_eq!(convergence.validation_losses.len(), 4);
+        // The weights go back to the ones from the first iteration, which
+        // had the lowest validation loss.
+        let best: f64 = lr.predict_slice(&[vec![1.0].into()])[0];
+        assert!((-(-best).ln_1p() - convergence.validation_losses[0]).abs() < 1e-12);
+        assert!(convergence.validation_losses[0] < convergence.validation_losses[3]);
+
+        let mut lr = Parameters::default()
+            .gradient_cap(1e-9)
+            .iteration_cap(5)
+            .build();
+        let convergence = lr.train(&training);
+        assert_eq!(convergence.stop, Stop::IterationCap);
+        assert!(convergence.validation_losses.is_empty());
+    }
+}
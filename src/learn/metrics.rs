@@ -62,9 +62,141 @@ pub fn roc_auc_score<T: Float + Default>(ytrue: &[T], yhat: &[T]) -> T {
     trapezoidal(positive_counts(ytrue, yhat))
 }
 
+/// Calculates how well each group of examples is ranked, as the fraction of
+/// pairs of a positive and a negative example in the group that are ordered
+/// correctly by their score (ties counting as half), averaged over all of the
+/// groups.  Each group is a list of `(score, label)`; groups that don't have
+/// both a positive and a negative example are ignored.  If no group has
+/// both, this is `0.5`, which is what random ordering would get.
+pub fn pairwise_accuracy<T: Float>(groups: &[Vec<(T, T)>]) -> T {
+    let half = T::one() / (T::one() + T::one());
+    let (sum, count) = groups
+        .iter()
+        .filter_map(|group| {
+            let positives = group.iter().filter(|(_, l)| !l.is_zero());
+            let negatives = || group.iter().filter(|(_, l)| l.is_zero());
+            let (correct, total) = positives
+                .flat_map(|(p, _)| negatives().map(move |(n, _)| (*p, *n)))
+                .map(|(p, n)| match p.partial_cmp(&n) {
+                    Some(Ordering::Greater) => T::one(),
+                    Some(Ordering::Equal) => half,
+                    _ => T::zero(),
+                })
+                .fold((T::zero(), T::zero()), |(c, t), v| (c + v, t + T::one()));
+            Some(correct / total).filter(|_| !total.is_zero())
+        })
+        .fold((T::zero(), T::zero()), |(s, c), v| (s + v, c + T::one()));
+
+    if count.is_zero() {
+        half
+    } else {
+        sum / count
+    }
+}
+
+/// Calculates the normalized discounted cumulative gain of the top `k`
+/// examples of each group, averaged over all of the groups.  Each group is
+/// a list of `(score, label)`, where the label is the relevance of the
+/// example; groups without any relevant examples are ignored.
+pub fn ndcg_at<T: Float>(groups: &[Vec<(T, T)>], k: usize) -> T {
+    let dcg = |labels: &mut dyn Iterator<Item = T>| {
+        labels
+            .take(k)
+            .enumerate()
+            .map(|(i, label)| label / T::from(i + 2).unwrap().log2())
+            .fold(T::zero(), T::add)
+    };
+
+    mean_over(groups, |group| {
+        let mut ideal = group.iter().map(|(_, l)| *l).collect::<Vec<_>>();
+        ideal.sort_by(|a, b| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+        let ideal = dcg(&mut ideal.into_iter());
+        let actual = dcg(&mut ranked(group).map(|(_, l)| l));
+        Some(actual / ideal).filter(|_| !ideal.is_zero())
+    })
+}
+
+/// Calculates the reciprocal of the rank of the first positive example in
+/// each group, averaged over all of the groups.  Groups without any positive
+/// examples are ignored.
+pub fn mean_reciprocal_rank<T: Float>(groups: &[Vec<(T, T)>]) -> T {
+    mean_over(groups, |group| {
+        ranked(group)
+            .position(|(_, l)| !l.is_zero())
+            .map(|i| T::one() / T::from(i + 1).unwrap())
+    })
+}
+
+/// Calculates the fraction of the top `k` examples of each group that are
+/// positive, averaged over all of the groups.  Groups without any positive
+/// examples are ignored.
+pub fn precision_at<T: Float>(groups: &[Vec<(T, T)>], k: usize) -> T {
+    mean_over(groups, |group| {
+        let hits = ranked(group).take(k).filter(|(_, l)| !l.is_zero()).count();
+        positives(group).map(|_| T::from(hits).unwrap() / T::from(k.max(1)).unwrap())
+    })
+}
+
+/// Calculates the fraction of the positive examples of each group that are
+/// in its top `k`, averaged over all of the groups.  Groups without any
+/// positive examples are ignored.
+pub fn recall_at<T: Float>(groups: &[Vec<(T, T)>], k: usize) -> T {
+    mean_over(groups, |group| {
+        let hits = ranked(group).take(k).filter(|(_, l)| !l.is_zero()).count();
+        positives(group).map(|total| T::from(hits).unwrap() / total)
+    })
+}
+
+/// Calculates the mean logistic loss of all of the examples in all of the
+/// groups, where the scores are the predicted probabilities.  Unlike the
+/// other metrics, lower is better.
+pub fn log_loss<T: Float>(groups: &[Vec<(T, T)>]) -> T {
+    let epsilon = T::epsilon();
+    let (sum, count) = groups
+        .iter()
+        .flatten()
+        .map(|(score, label)| {
+            let score = score.max(epsilon).min(T::one() - epsilon);
+            *label * score.ln() + (T::one() - *label) * score.neg().ln_1p()
+        })
+        .fold((T::zero(), T::zero()), |(s, c), v| (s + v, c + T::one()));
+
+    if count.is_zero() {
+        T::zero()
+    } else {
+        sum.neg() / count
+    }
+}
+
+/// The examples of the group, highest score first.
+fn ranked<T: Float>(group: &[(T, T)]) -> impl Iterator<Item = (T, T)> {
+    let mut group = group.to_vec();
+    group.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+    group.into_iter()
+}
+
+/// The number of positive examples in the group, if there are any.
+fn positives<T: Float>(group: &[(T, T)]) -> Option<T> {
+    let count = group.iter().filter(|(_, l)| !l.is_zero()).count();
+    Some(T::from(count).unwrap()).filter(|_| count > 0)
+}
+
+fn mean_over<T: Float>(groups: &[Vec<(T, T)>], f: impl Fn(&[(T, T)]) -> Option<T>) -> T {
+    let (sum, count) = groups
+        .iter()
+        .filter_map(|group| f(group))
+        .fold((T::zero(), T::zero()), |(s, c), v| (s + v, c + T::one()));
+
+    if count.is_zero() {
+        T::zero()
+    } else {
+        sum / count
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::roc_auc_score;
+    use super::*;
 
     fn close(x: f32, y: f32, e: f32) -> bool {
         let abs_x = x.abs();
@@ -97,4 +229,38 @@ mod tests {
         dbg!(auc);
         assert!(close(auc, 0.75, 0.00001));
     }
+
+    #[test]
+    fn test_pairwise_accuracy() {
+        let groups = vec![
+            vec![(0.9, 1.0), (0.5, 0.0), (0.95, 0.0)],
+            vec![(0.3, 1.0), (0.1, 0.0), (0.3, 0.0)],
+            // Ignored, since there's no negative example.
+            vec![(0.2, 1.0)],
+        ];
+        let accuracy = pairwise_accuracy(&groups);
+        assert!(close(accuracy, 0.625, 0.00001));
+    }
+
+    #[test]
+    fn test_ranking_metrics() {
+        let groups = vec![
+            vec![(0.9, 0.0), (0.8, 1.0), (0.1, 0.0)],
+            vec![(0.7, 1.0), (0.2, 0.0)],
+            // Ignored by everything but the log loss.
+            vec![(0.5, 0.0)],
+        ];
+
+        let ndcg = 0.5 * (1.0 / 3.0f32.log2() + 1.0);
+        assert!(close(ndcg_at(&groups, 3), ndcg, 0.00001));
+        assert!(close(mean_reciprocal_rank(&groups), 0.75, 0.00001));
+        assert!(close(precision_at(&groups, 1), 0.5, 0.00001));
+        assert!(close(recall_at(&groups, 1), 0.5, 0.00001));
+        assert!(close(recall_at(&groups, 2), 1.0, 0.00001));
+
+        let loss =
+            -(0.1f32.ln() + 0.8f32.ln() + 0.9f32.ln() + 0.7f32.ln() + 0.8f32.ln() + 0.5f32.ln())
+                / 6.0;
+        assert!(close(log_loss(&groups), loss, 0.0001));
+    }
 }
@@ -1,17 +1,95 @@
 use num_traits::Float;
 
+pub use self::sparse::SparseVector;
 pub use self::vector::{combine, Vector};
 
+pub mod calibration;
+pub mod factorization;
+pub mod ftrl;
 pub mod logistic;
 pub mod metrics;
+pub mod optimizer;
+pub mod search;
+pub mod sparse;
 pub mod vector;
 
+/// A learning algorithm.  The examples it learns from are given with their
+/// target, and the weight of the example; an example with a weight of two
+/// counts as much towards the loss as two of the same example would.
 pub trait Algorithm<T: Float + Default + 'static> {
-    fn fit(&mut self, examples: &[(Vector<T>, T)]);
-    fn train(&mut self, examples: &[(Vector<T>, T)]);
+    fn fit(&mut self, examples: &[(SparseVector<T>, T, T)]);
+
+    fn train(&mut self, examples: &[(SparseVector<T>, T, T)]) -> Convergence<T> {
+        self.train_with_validation(examples, &[])
+    }
+
+    /// Trains on the examples, measuring the loss on the validation examples
+    /// after every iteration.  Algorithms that support early stopping use
+    /// this to decide when to stop.
+    fn train_with_validation(
+        &mut self,
+        examples: &[(SparseVector<T>, T, T)],
+        validation: &[(SparseVector<T>, T, T)],
+    ) -> Convergence<T>;
+
     fn predict_iter<'o>(
         &'o self,
-        iter: Box<dyn Iterator<Item = &'o Vector<T>> + 'o>,
+        iter: Box<dyn Iterator<Item = &'o SparseVector<T>> + 'o>,
     ) -> Box<dyn Iterator<Item = T> + 'o>;
-    fn predict_slice(&self, examples: &[Vector<T>]) -> Vec<T>;
+    fn predict_slice(&self, examples: &[SparseVector<T>]) -> Vec<T>;
+}
+
+/// How training went: the loss after each iteration, and why it stopped.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Convergence<T> {
+    pub iterations: usize,
+    /// The loss on the training examples after each iteration.
+    pub losses: Vec<T>,
+    /// The loss on the validation examples after each iteration, if there
+    /// were any.
+    pub validation_losses: Vec<T>,
+    pub stop: Stop,
+}
+
+/// The reason training stopped.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stop {
+    /// The model stopped improving on the training examples.
+    #[default]
+    Converged,
+    /// The maximum number of iterations was reached.
+    IterationCap,
+    /// No step could be found that decreased the loss.
+    StepExhausted,
+    /// The loss on the validation examples stopped improving.
+    EarlyStopped,
+}
+
+/// The parameters of one of the learning algorithms, along with which
+/// algorithm they are for.  This is kept with every trained model, so that
+/// it is known how the model was trained.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Parameters<T: Float + Default + 'static> {
+    Logistic(logistic::Parameters<T>),
+    Factorization(factorization::Parameters<T>),
+}
+
+impl<T: Float + Default + 'static> Default for Parameters<T> {
+    fn default() -> Parameters<T> {
+        Parameters::Logistic(Default::default())
+    }
+}
+
+impl<T: Float + Default + 'static> Parameters<T> {
+    /// The number of iterations training continues for without the
+    /// validation loss improving, if the algorithm stops early.
+    pub fn patience(&self) -> Option<usize> {
+        match self {
+            Parameters::Logistic(parameters) => parameters.patience,
+            Parameters::Factorization(_) => None,
+        }
+    }
 }
@@ -0,0 +1,159 @@
+use num_traits::Float;
+
+use super::ftrl;
+use super::Vector;
+
+/// How the weights of a model are updated from the gradient of the loss.
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Optimizer<T: Float + Default + 'static> {
+    /// Full-batch gradient descent, where the size of each step is chosen
+    /// with the Barzilai-Borwein method.
+    #[default]
+    GradientDescent,
+    /// Mini-batch stochastic gradient descent, with a fixed learning rate.
+    Sgd,
+    /// Mini-batch gradient descent, where the learning rate of each feature
+    /// shrinks with the sum of the squares of its gradients.  Features that
+    /// are rarely seen keep a high learning rate.
+    Adagrad,
+    /// Mini-batch gradient descent, using running averages of the gradient
+    /// and its square for each feature.
+    Adam,
+    /// Mini-batch FTRL-Proximal.  This uses its own parameters, rather than
+    /// the learning rate.
+    Ftrl(ftrl::Parameters<T>),
+}
+
+/// The per-feature state an optimizer keeps between steps.  What the values
+/// mean depends on the optimizer: for Adagrad, `second` is the sum of the
+/// squared gradients; for Adam, `first` and `second` are the averages of
+/// the gradient and its square; for FTRL, they're `z` and `n`.
+#[derive(Debug, Clone)]
+pub struct OptimizerState<T> {
+    steps: i32,
+    first: Vector<T>,
+    second: Vector<T>,
+}
+
+impl<T> Default for OptimizerState<T> {
+    fn default() -> OptimizerState<T> {
+        OptimizerState {
+            steps: 0,
+            first: Vector::empty(),
+            second: Vector::empty(),
+        }
+    }
+}
+
+const ADAM_DECAY: (f64, f64) = (0.9, 0.999);
+const EPSILON: f64 = 1e-8;
+
+impl<T: Float + Default + 'static> Optimizer<T> {
+    /// Whether this optimizer steps over all of the examples at once, rather
+    /// than in mini-batches.
+    pub fn is_batch(&self) -> bool {
+        matches!(self, Optimizer::GradientDescent)
+    }
+
+    /// Updates the weights with the gradient of a single batch.
+    pub fn step(
+        &self,
+        state: &mut OptimizerState<T>,
+        weights: &mut Vector<T>,
+        gradient: &Vector<T>,
+        learning_rate: T,
+    ) {
+        let len = weights.len().max(gradient.len());
+        if len == 0 {
+            return;
+        }
+        weights.resize_to(len - 1);
+
+        while state.first.len() < len {
+            let first = match self {
+                Optimizer::Ftrl(parameters) => parameters.initial_z(weights[state.first.len()]),
+                _ => T::zero(),
+            };
+            state.first.push(first);
+            state.second.push(T::zero());
+        }
+
+        state.steps += 1;
+        let epsilon = T::from(EPSILON).unwrap();
+        let (beta1, beta2) = (
+            T::from(ADAM_DECAY.0).unwrap(),
+            T::from(ADAM_DECAY.1).unwrap(),
+        );
+
+        for (idx, weight) in weights.iter_mut().enumerate() {
+            let g = gradient.element(idx);
+            let first = &mut state.first[idx];
+            let second = &mut state.second[idx];
+
+            match self {
+                Optimizer::GradientDescent | Optimizer::Sgd => {
+                    *weight = *weight - learning_rate * g;
+                }
+                Optimizer::Adagrad => {
+                    *second = *second + g.powi(2);
+                    *weight = *weight - learning_rate * g / (second.sqrt() + epsilon);
+                }
+                Optimizer::Adam => {
+                    *first = beta1 * *first + (T::one() - beta1) * g;
+                    *second = beta2 * *second + (T::one() - beta2) * g.powi(2);
+                    let first = *first / (T::one() - beta1.powi(state.steps));
+                    let second = *second / (T::one() - beta2.powi(state.steps));
+                    *weight = *weight - learning_rate * first / (second.sqrt() + epsilon);
+                }
+                Optimizer::Ftrl(parameters) => {
+                    parameters.accumulate(first, second, *weight, g);
+                    *weight = parameters.weight(*first, *second);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learn::logistic::Parameters;
+    use crate::learn::{Algorithm, SparseVector};
+
+    #[test]
+    fn it_trains_with_each_optimizer() {
+        let examples = (0..64)
+            .map(|i| {
+                let positive = i % 2 == 0;
+                let example = if positive {
+                    vec![1.0, 0.0]
+                } else {
+                    vec![0.0, 1.0]
+                };
+                (example.into(), if positive { 1.0 } else { 0.0 }, 1.0)
+            })
+            .collect::<Vec<(SparseVector<f64>, f64, f64)>>();
+
+        let optimizers = vec![
+            Optimizer::Sgd,
+            Optimizer::Adagrad,
+            Optimizer::Adam,
+            Optimizer::Ftrl(ftrl::Parameters::default().alpha(0.5)),
+        ];
+
+        for optimizer in optimizers {
+            let mut lr = Parameters::default()
+                .learning_rate(0.1)
+                .gradient_cap(0.01)
+                .iteration_cap(200)
+                .optimizer(optimizer)
+                .batch_size(8)
+                .build();
+            lr.train(&examples);
+            let predictions = lr.predict_slice(&[vec![1.0, 0.0].into(), vec![0.0, 1.0].into()]);
+            assert!(predictions[0] > 0.7, "{:?}: {:?}", optimizer, predictions);
+            assert!(predictions[1] < 0.3, "{:?}: {:?}", optimizer, predictions);
+        }
+    }
+}
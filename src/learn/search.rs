@@ -0,0 +1,104 @@
+use num_traits::Float;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use super::logistic::Parameters;
+
+/// The values to try for each of the logistic regression parameters, when
+/// searching for the best ones.  Any parameter without values to try is
+/// left as it is in the base parameters.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SearchSpace<T: Float + Default + 'static> {
+    pub learning_rate: Vec<T>,
+    pub gradient_cap: Vec<T>,
+    pub iteration_cap: Vec<usize>,
+    pub l1_regularization: Vec<T>,
+    pub l2_regularization: Vec<T>,
+}
+
+impl<T: Float + Default + 'static> SearchSpace<T> {
+    /// Every combination of the values to try.
+    pub fn grid(&self, base: Parameters<T>) -> Vec<Parameters<T>> {
+        let list = vec![base];
+        let list = expand(list, &self.learning_rate, Parameters::learning_rate);
+        let list = expand(list, &self.gradient_cap, Parameters::gradient_cap);
+        let list = expand(list, &self.iteration_cap, Parameters::iteration_cap);
+        let list = expand(list, &self.l1_regularization, Parameters::l1_regularization);
+        expand(list, &self.l2_regularization, Parameters::l2_regularization)
+    }
+
+    /// The given number of combinations, each made by picking one of the
+    /// values to try for each parameter at random.
+    pub fn random(
+        &self,
+        base: Parameters<T>,
+        count: usize,
+        rng: &mut impl Rng,
+    ) -> Vec<Parameters<T>> {
+        (0..count)
+            .map(|_| {
+                let params = pick(base, &self.learning_rate, Parameters::learning_rate, rng);
+                let params = pick(params, &self.gradient_cap, Parameters::gradient_cap, rng);
+                let params = pick(params, &self.iteration_cap, Parameters::iteration_cap, rng);
+                let params = pick(
+                    params,
+                    &self.l1_regularization,
+                    Parameters::l1_regularization,
+                    rng,
+                );
+                pick(
+                    params,
+                    &self.l2_regularization,
+                    Parameters::l2_regularization,
+                    rng,
+                )
+            })
+            .collect()
+    }
+}
+
+fn expand<T: Float + Default + 'static, V: Copy>(
+    list: Vec<Parameters<T>>,
+    values: &[V],
+    apply: fn(Parameters<T>, V) -> Parameters<T>,
+) -> Vec<Parameters<T>> {
+    if values.is_empty() {
+        return list;
+    }
+
+    list.into_iter()
+        .flat_map(|params| values.iter().map(move |value| apply(params, *value)))
+        .collect()
+}
+
+fn pick<T: Float + Default + 'static, V: Copy>(
+    params: Parameters<T>,
+    values: &[V],
+    apply: fn(Parameters<T>, V) -> Parameters<T>,
+    rng: &mut impl Rng,
+) -> Parameters<T> {
+    match values.choose(rng) {
+        Some(value) => apply(params, *value),
+        None => params,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_builds_the_grid() {
+        let space = SearchSpace {
+            learning_rate: vec![0.1, 1.0],
+            l2_regularization: vec![0.0, 0.01, 0.1],
+            ..Default::default()
+        };
+        let grid = space.grid(Parameters::<f64>::default());
+        assert_eq!(grid.len(), 6);
+
+        let empty = SearchSpace::default().grid(Parameters::<f64>::default());
+        assert_eq!(empty.len(), 1);
+    }
+}
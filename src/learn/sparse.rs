@@ -0,0 +1,136 @@
+use std::iter::FromIterator;
+
+use num_traits::Float;
+
+use super::Vector;
+
+/// A vector that only keeps track of its non-zero elements, as pairs of
+/// the index of the element and its value, ordered by index.  Examples are
+/// represented with these, since each example usually only has a small
+/// subset of all of the features a model knows about; the weights of a
+/// model are kept as a (dense) [`Vector`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseVector<T>(Vec<(usize, T)>);
+
+impl<T> SparseVector<T> {
+    pub fn empty() -> SparseVector<T> {
+        SparseVector(Vec::new())
+    }
+
+    /// The number of non-zero elements in the vector.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The length that a dense vector would need to be to contain all of
+    /// the elements of this one; that is, one more than the highest index.
+    pub fn dimension(&self) -> usize {
+        self.0.last().map(|(idx, _)| idx + 1).unwrap_or(0)
+    }
+}
+
+impl<T: Float + Default> SparseVector<T> {
+    /// Iterates over the indices and values of the non-zero elements.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, T)> + '_ {
+        self.0.iter().cloned()
+    }
+
+    /// Retrieves the element at the given index, which is zero if it isn't
+    /// stored.
+    pub fn element(&self, idx: usize) -> T {
+        match self.0.binary_search_by_key(&idx, |(i, _)| *i) {
+            Ok(pos) => self.0[pos].1,
+            Err(_) => T::zero(),
+        }
+    }
+
+    /// Calculates the dot product of this vector with a dense vector.  This
+    /// only needs to look at the non-zero elements of this vector.
+    pub fn dot(&self, dense: &Vector<T>) -> T {
+        self.iter()
+            .map(|(idx, value)| value * dense.element(idx))
+            .fold(T::zero(), T::add)
+    }
+
+    /// Subtracts the other vector from this one.
+    pub fn sub(&self, other: &SparseVector<T>) -> SparseVector<T> {
+        self.iter()
+            .chain(other.iter().map(|(idx, value)| (idx, value.neg())))
+            .collect()
+    }
+
+    pub fn neg(&self) -> SparseVector<T> {
+        SparseVector(self.iter().map(|(idx, value)| (idx, value.neg())).collect())
+    }
+
+    pub fn to_dense(&self) -> Vector<T> {
+        let mut dense = Vector::empty();
+        for (idx, value) in self.iter() {
+            dense.set_element(idx, value);
+        }
+        dense
+    }
+}
+
+impl<T> Default for SparseVector<T> {
+    fn default() -> SparseVector<T> {
+        SparseVector::empty()
+    }
+}
+
+/// Collects the elements into a sparse vector.  The elements don't need to
+/// be in order; any elements with the same index are summed together, and
+/// any that are zero are dropped.
+impl<T: Float + Default> FromIterator<(usize, T)> for SparseVector<T> {
+    fn from_iter<I: IntoIterator<Item = (usize, T)>>(iter: I) -> Self {
+        let mut list = iter.into_iter().collect::<Vec<_>>();
+        list.sort_by_key(|(idx, _)| *idx);
+        let mut result: Vec<(usize, T)> = Vec::with_capacity(list.len());
+        for (idx, value) in list {
+            match result.last_mut() {
+                Some((last, sum)) if *last == idx => *sum = *sum + value,
+                _ => result.push((idx, value)),
+            }
+        }
+        result.retain(|(_, value)| !value.is_zero());
+        SparseVector(result)
+    }
+}
+
+impl<T: Float + Default> From<Vector<T>> for SparseVector<T> {
+    fn from(dense: Vector<T>) -> SparseVector<T> {
+        dense.iter().cloned().enumerate().collect()
+    }
+}
+
+impl<T: Float + Default> From<Vec<T>> for SparseVector<T> {
+    fn from(dense: Vec<T>) -> SparseVector<T> {
+        dense.into_iter().enumerate().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_combines_with_dense_vectors() {
+        let sparse = vec![(4, 2.0), (1, 1.0), (4, 1.0), (2, 0.0)]
+            .into_iter()
+            .collect::<SparseVector<f64>>();
+        assert_eq!(sparse, SparseVector(vec![(1, 1.0), (4, 3.0)]));
+        assert_eq!(sparse.dimension(), 5);
+        assert_eq!(sparse.element(4), 3.0);
+        assert_eq!(sparse.element(3), 0.0);
+
+        let dense = Vector::from(vec![1.0, 2.0, 3.0]);
+        assert_eq!(sparse.dot(&dense), 2.0);
+
+        let other = SparseVector::from(vec![0.0, 1.0, 5.0]);
+        assert_eq!(sparse.sub(&other), SparseVector(vec![(2, -5.0), (4, 3.0)]));
+    }
+}
@@ -16,6 +16,7 @@ pub mod http;
 pub mod learn;
 mod ord;
 pub mod recommend;
+mod replay;
 pub mod storage;
 
 fn main() {
@@ -25,5 +26,15 @@ fn main() {
     let file = config::File::with_name("vilkas").required(false);
     config.merge(env).expect("could not load env config");
     config.merge(file).expect("could not load file config");
-    http::run(config);
+
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("replay") => {
+            if let Err(e) = replay::run(config, args) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        _ => http::run(config),
+    }
 }
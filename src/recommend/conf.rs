@@ -1,4 +1,12 @@
-use crate::storage::{Example, FeatureList};
+use crate::learn::calibration::Calibration;
+use crate::learn::logistic::Parameters;
+use crate::learn::search::SearchSpace;
+use crate::learn::{factorization, ftrl, metrics};
+use crate::recommend::{Filter, Rejection, TrainReport};
+use crate::storage::{Activity, Example, FeatureList, Impression, Model, Scaling};
+use failure::{format_err, Error};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,6 +18,69 @@ pub struct PartConfig {
     pub meta_features: HashMap<String, MetaFeature>,
     #[serde(default = "defaults::upgrade_chance")]
     pub upgrade_chance: f64,
+    #[serde(default)]
+    pub learner: Learner,
+    #[serde(default)]
+    pub objective: Objective,
+    /// How newly trained models are compared to the current ones.  If this
+    /// isn't given, it depends on the objective.
+    #[serde(default)]
+    pub criterion: Option<Criterion>,
+    /// If given, the part's model is also updated every time an item is
+    /// chosen, instead of only when it's trained.
+    #[serde(default)]
+    pub online: Option<OnlineConfig>,
+    /// If given, meta features are hashed into a fixed number of buckets,
+    /// so that meta with many distinct values doesn't give the model an
+    /// unbounded number of features.
+    #[serde(default)]
+    pub hashing: Option<HashingConfig>,
+    /// How much each example counts for when training.
+    #[serde(default)]
+    pub weighting: WeightingConfig,
+    /// If given, only this fraction of the examples that weren't chosen are
+    /// used to train the part's models.  The models are corrected for this
+    /// when they're served.
+    #[serde(default)]
+    pub downsampling: Option<f64>,
+    /// Crosses between features, which are added to the features of every
+    /// example, before they are hashed.
+    #[serde(default)]
+    pub crosses: Vec<Cross>,
+    /// If given, the features are standardized with statistics computed
+    /// when the part is trained, which are stored with its model.
+    #[serde(default)]
+    pub standardization: Option<Standardization>,
+    /// If given, the predictions of the part's models are calibrated after
+    /// training, on the examples that were held out of training.
+    #[serde(default)]
+    pub calibration: Option<CalibrationMethod>,
+    /// How the recommended items are explored, so that items the model is
+    /// unsure about still get shown.
+    #[serde(default)]
+    pub exploration: Exploration,
+    /// If given, exploration is seeded with this on every request, instead
+    /// of randomly, so that it can be reproduced.  This is meant for tests.
+    #[serde(default)]
+    pub exploration_seed: Option<u64>,
+    /// If true, the models are trained with a `position:ln1p` feature for
+    /// the position each example was shown at, which is left out when
+    /// recommending.  This lets the model learn how much being shown higher
+    /// up matters on its own, instead of crediting it to the other features.
+    #[serde(default)]
+    pub position_feature: bool,
+    /// If true, the items the user has already viewed aren't recommended to
+    /// them.
+    #[serde(default)]
+    pub exclude_history: bool,
+    /// If given, only items that match this are recommended, unless the
+    /// request gives its own filter.
+    #[serde(default)]
+    pub filter: Option<Filter>,
+    /// When the items are filtered, up to this many times the max candidate
+    /// count are looked at, to make up for the ones that are filtered out.
+    #[serde(default = "defaults::filter_candidate_factor")]
+    pub filter_candidate_factor: usize,
 }
 
 mod defaults {
@@ -19,6 +90,48 @@ mod defaults {
     pub fn upgrade_chance() -> f64 {
         0.10
     }
+    pub fn retain_versions() -> usize {
+        16
+    }
+    pub fn cutoff() -> usize {
+        10
+    }
+    pub fn holdout_ratio() -> f64 {
+        1.0 / 3.0
+    }
+    pub fn stopping_ratio() -> f64 {
+        0.1
+    }
+    pub fn search_samples() -> usize {
+        16
+    }
+    pub fn persist_every() -> usize {
+        32
+    }
+    pub fn persist_interval() -> u64 {
+        30
+    }
+    pub fn weight() -> f64 {
+        1.0
+    }
+    pub fn hash_buckets() -> usize {
+        1 << 12
+    }
+    pub fn hash_prefix() -> String {
+        "meta:".to_owned()
+    }
+    pub fn epsilon() -> f64 {
+        0.05
+    }
+    pub fn prior_weight() -> f64 {
+        10.0
+    }
+    pub fn ucb_scale() -> f64 {
+        1.0
+    }
+    pub fn filter_candidate_factor() -> usize {
+        8
+    }
 }
 
 impl Default for PartConfig {
@@ -27,11 +140,567 @@ impl Default for PartConfig {
             max_candidate_count: defaults::max_candidate_count(),
             meta_features: HashMap::new(),
             upgrade_chance: defaults::upgrade_chance(),
+            learner: Learner::default(),
+            objective: Objective::default(),
+            criterion: None,
+            online: None,
+            hashing: None,
+            weighting: WeightingConfig::default(),
+            downsampling: None,
+            crosses: Vec::new(),
+            standardization: None,
+            calibration: None,
+            exploration: Exploration::default(),
+            exploration_seed: None,
+            position_feature: false,
+            exclude_history: false,
+            filter: None,
+            filter_candidate_factor: defaults::filter_candidate_factor(),
+        }
+    }
+}
+
+/// How trained models are validated, before deciding whether or not to
+/// promote them.  This is loaded from `recommend.core.validation`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ValidationConfig {
+    /// The fraction of the activities that are held out of training, to
+    /// measure the performance of the models on.
+    #[serde(default = "defaults::holdout_ratio")]
+    pub holdout_ratio: f64,
+    /// If given, performance is measured with k-fold cross-validation over
+    /// this many folds, instead of a single holdout.  The new model is then
+    /// trained on all of the activities.
+    #[serde(default)]
+    pub folds: Option<usize>,
+    /// The seed the activities are shuffled with before they're split, and
+    /// that negative examples are downsampled with, so that training can be
+    /// reproduced.
+    #[serde(default)]
+    pub seed: u64,
+    /// The fraction of the activities being trained on that are set aside
+    /// to decide when to stop training, if the parameters stop early.
+    #[serde(default = "defaults::stopping_ratio")]
+    pub stopping_ratio: f64,
+    /// What a newly trained model has to pass before it can be promoted,
+    /// even if it performed better.
+    #[serde(default)]
+    pub guards: PromotionGuards,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> ValidationConfig {
+        ValidationConfig {
+            holdout_ratio: defaults::holdout_ratio(),
+            folds: None,
+            seed: 0,
+            stopping_ratio: defaults::stopping_ratio(),
+            guards: PromotionGuards::default(),
+        }
+    }
+}
+
+/// The checks a newly trained model has to pass before it's promoted.  Models
+/// with weights that aren't finite are always rejected; the rest of the
+/// checks are only made if they're configured.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PromotionGuards {
+    /// The minimum number of examples the model has to be validated on.
+    #[serde(default)]
+    pub min_holdout: usize,
+    /// The minimum number of chosen examples the model has to be validated
+    /// on.
+    #[serde(default)]
+    pub min_positives: usize,
+    /// The worst performance the model can have, by the part's criterion.
+    /// For the log loss, this is the highest; for the rest, the lowest.
+    #[serde(default)]
+    pub min_performance: Option<f64>,
+    /// The largest magnitude any weight of the model can have.
+    #[serde(default)]
+    pub max_weight: Option<f64>,
+    /// The largest change to any weight, relative to its magnitude in the
+    /// current model.  Weights that were smaller than one in magnitude are
+    /// compared to one instead, so that new features can still be learned.
+    /// Weights are compared for the features' values before they're scaled.
+    #[serde(default)]
+    pub max_change: Option<f64>,
+}
+
+impl PromotionGuards {
+    /// Why the newly trained model shouldn't be promoted over the current
+    /// one, if it shouldn't.
+    pub fn check(
+        &self,
+        report: &TrainReport,
+        current: &Model,
+        trained: &Model,
+    ) -> Option<Rejection> {
+        let factors = trained
+            .factors
+            .iter()
+            .filter(|(_, factors)| factors.iter().any(|v| !v.is_finite()))
+            .map(|(feature, _)| feature.to_owned());
+        let non_finite = trained
+            .weights
+            .iter()
+            .filter(|(_, weight)| !weight.is_finite())
+            .map(|(feature, _)| feature.to_string())
+            .chain(factors)
+            .next();
+        if let Some(feature) = non_finite {
+            return Some(Rejection::NonFinite { feature });
+        }
+
+        if report.holdout < self.min_holdout {
+            return Some(Rejection::SmallHoldout {
+                holdout: report.holdout,
+                minimum: self.min_holdout,
+            });
+        }
+
+        if report.positives < self.min_positives {
+            return Some(Rejection::FewPositives {
+                positives: report.positives,
+                minimum: self.min_positives,
+            });
+        }
+
+        if let Some(threshold) = self.min_performance {
+            let performance = report.new_performance;
+            if report.criterion.improved(performance, threshold) || performance.is_nan() {
+                return Some(Rejection::LowPerformance {
+                    performance,
+                    threshold,
+                });
+            }
+        }
+
+        if let Some(maximum) = self.max_weight {
+            let large = trained.weights.iter().find(|(_, w)| w.abs() > maximum);
+            if let Some((feature, weight)) = large {
+                return Some(Rejection::LargeWeight {
+                    feature: feature.to_string(),
+                    weight: *weight,
+                    maximum,
+                });
+            }
+        }
+
+        if let Some(maximum) = self.max_change {
+            // The models can scale the features differently, so the weights
+            // are compared for the features' values before they're scaled.
+            let large = current
+                .weights
+                .union(&trained.weights)
+                .map(|(feature, from, to)| {
+                    let from = current.unscaled_weight(&feature, from);
+                    let to = trained.unscaled_weight(&feature, to);
+                    (feature, from, to)
+                })
+                .find(|(_, from, to)| (to - from).abs() / from.abs().max(1.0) > maximum);
+            if let Some((feature, from, to)) = large {
+                return Some(Rejection::LargeChange {
+                    feature: feature.into_owned(),
+                    from,
+                    to,
+                    maximum,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+/// How a part's model learns from each chosen activity as it happens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OnlineConfig {
+    #[serde(default)]
+    pub parameters: ftrl::Parameters<f64>,
+    /// The number of activities to learn from before the model is written
+    /// back to storage.
+    #[serde(default = "defaults::persist_every")]
+    pub persist_every: usize,
+    /// The number of seconds after which the model is written back to
+    /// storage, even if it hasn't learned from enough activities yet.
+    #[serde(default = "defaults::persist_interval")]
+    pub persist_interval: u64,
+}
+
+/// The rules that the weight of each training example is assigned with.  The
+/// weight of a chosen example depends on the type of event it was chosen
+/// with, and every example's weight decays with the age of its activity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WeightingConfig {
+    /// The weight of chosen examples of each type of event.
+    #[serde(default)]
+    pub events: HashMap<String, f64>,
+    /// The weight of chosen examples with an event that isn't in `events`,
+    /// or without one.
+    #[serde(default = "defaults::weight")]
+    pub positive: f64,
+    /// The weight of examples that weren't chosen.  Since most examples
+    /// aren't, lowering this keeps them from dominating training.
+    #[serde(default = "defaults::weight")]
+    pub negative: f64,
+    /// If given, the weight of an example halves every this many seconds
+    /// since its activity happened.
+    #[serde(default)]
+    pub half_life: Option<u64>,
+    /// If given, each example is also weighted by the inverse of the
+    /// probability that exploration showed it where it was, up to this
+    /// much, so that training isn't biased towards what the part already
+    /// recommends.
+    #[serde(default)]
+    pub max_inverse_propensity: Option<f64>,
+}
+
+impl Default for WeightingConfig {
+    fn default() -> WeightingConfig {
+        WeightingConfig {
+            events: HashMap::new(),
+            positive: defaults::weight(),
+            negative: defaults::weight(),
+            half_life: None,
+            max_inverse_propensity: None,
+        }
+    }
+}
+
+impl WeightingConfig {
+    /// The weight of an example of the activity that was shown with the
+    /// impression (if it was recorded), as of the given time (in
+    /// milliseconds since the unix epoch).
+    pub fn weight(
+        &self,
+        activity: &Activity,
+        impression: Option<&Impression>,
+        positive: bool,
+        now: u64,
+    ) -> f64 {
+        let base = if !positive {
+            self.negative
+        } else {
+            activity
+                .event
+                .as_ref()
+                .and_then(|event| self.events.get(event))
+                .cloned()
+                .unwrap_or(self.positive)
+        };
+
+        let base = match (self.max_inverse_propensity, impression) {
+            (Some(max), Some(impression)) => base * (1.0 / impression.propensity).min(max),
+            _ => base,
+        };
+
+        match self.half_life {
+            Some(half_life) if half_life > 0 => {
+                let age = now.saturating_sub(activity.created) as f64 / 1000.0;
+                base * 0.5f64.powf(age / half_life as f64)
+            }
+            _ => base,
+        }
+    }
+}
+
+/// A cross between features.  This adds a feature for every combination of
+/// the features that it matches, named `cross:` followed by the keys of the
+/// features in the combination, joined by `*` for products, or `&` for
+/// conjunctions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Cross {
+    /// The features to cross.  Each is either the key of a feature, such as
+    /// `list:near:rank`, or a prefix of keys up to a `:`, such as
+    /// `list:top:hour`, which matches every feature under it.
+    pub features: Vec<String>,
+    #[serde(default)]
+    pub kind: CrossKind,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CrossKind {
+    /// The value of the cross is the product of the values of the features.
+    #[default]
+    Product,
+    /// The value of the cross is one if every feature is non-zero, and zero
+    /// otherwise.
+    Conjunction,
+}
+
+impl Cross {
+    fn extract(&self, list: &FeatureList) -> Vec<(String, f64)> {
+        if self.features.is_empty() {
+            return vec![];
+        }
+
+        let separator = match self.kind {
+            CrossKind::Product => "*",
+            CrossKind::Conjunction => "&",
+        };
+        let mut crossed = vec![(String::new(), 1.0)];
+
+        for feature in &self.features {
+            let matching = list
+                .iter()
+                .filter(|(key, _)| {
+                    key.as_ref() == feature
+                        || (key.starts_with(&feature[..]) && key[feature.len()..].starts_with(':'))
+                })
+                .collect::<Vec<_>>();
+
+            crossed = crossed
+                .iter()
+                .flat_map(|(name, value)| {
+                    matching.iter().map(move |(key, other)| {
+                        let name = if name.is_empty() {
+                            key.to_string()
+                        } else {
+                            format!("{}{}{}", name, separator, key)
+                        };
+                        let value = match self.kind {
+                            CrossKind::Product => value * *other,
+                            CrossKind::Conjunction if *value != 0.0 && **other != 0.0 => 1.0,
+                            CrossKind::Conjunction => 0.0,
+                        };
+                        (name, value)
+                    })
+                })
+                .collect();
+        }
+
+        crossed
+            .into_iter()
+            .map(|(name, value)| (format!("cross:{}", name), value))
+            .collect()
+    }
+}
+
+/// How the predictions of a model are calibrated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CalibrationMethod {
+    Platt,
+    Isotonic,
+}
+
+impl CalibrationMethod {
+    /// Fits the calibration to the predictions, given as `(prediction,
+    /// label)`.
+    pub fn fit(self, scored: &[(f64, f64)]) -> Calibration<f64> {
+        match self {
+            CalibrationMethod::Platt => Calibration::platt(scored),
+            CalibrationMethod::Isotonic => Calibration::isotonic(scored),
+        }
+    }
+}
+
+/// How features are standardized.  Only the features an example has are
+/// standardized, so features that are missing from an example stay missing.
+/// Features that always have the same value when they're present, like the
+/// values of meta, are left as they are.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Standardization {
+    /// Features are shifted by their mean, and scaled by their standard
+    /// deviation.
+    ZScore,
+    /// Features are shifted by their minimum, and scaled by the difference
+    /// between their maximum and minimum, so that they range from zero to
+    /// one.
+    MinMax,
+}
+
+impl Standardization {
+    /// Computes the scaling of each feature from the values it has in the
+    /// given lists.  Features whose values never vary don't get a scaling,
+    /// since it would turn them into zero.
+    pub fn fit<'l>(
+        self,
+        lists: impl Iterator<Item = &'l FeatureList<'static>>,
+    ) -> HashMap<String, Scaling> {
+        // The count, sum, sum of squares, minimum, and maximum of each
+        // feature.
+        let mut stats = HashMap::<&str, (f64, f64, f64, f64, f64)>::new();
+        for (key, value) in lists.flat_map(|list| list.iter()) {
+            let value = *value;
+            let entry = stats.entry(key.as_ref()).or_insert((
+                0.0,
+                0.0,
+                0.0,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+            ));
+            entry.0 += 1.0;
+            entry.1 += value;
+            entry.2 += value.powi(2);
+            entry.3 = entry.3.min(value);
+            entry.4 = entry.4.max(value);
+        }
+
+        stats
+            .into_iter()
+            .filter(|(_, (_, _, _, min, max))| max > min)
+            .map(|(key, (count, sum, squares, min, max))| {
+                let scaling = match self {
+                    Standardization::ZScore => {
+                        let mean = sum / count;
+                        let variance = (squares / count - mean.powi(2)).max(0.0);
+                        Scaling {
+                            shift: mean,
+                            scale: variance.sqrt(),
+                        }
+                    }
+                    Standardization::MinMax => Scaling {
+                        shift: min,
+                        scale: max - min,
+                    },
+                };
+                (key.to_owned(), scaling)
+            })
+            .collect()
+    }
+}
+
+/// How the features of a part are hashed into buckets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HashingConfig {
+    #[serde(default = "defaults::hash_buckets")]
+    pub buckets: usize,
+    /// Only features with keys that start with this are hashed.
+    #[serde(default = "defaults::hash_prefix")]
+    pub prefix: String,
+}
+
+impl Default for HashingConfig {
+    fn default() -> HashingConfig {
+        HashingConfig {
+            buckets: defaults::hash_buckets(),
+            prefix: defaults::hash_prefix(),
+        }
+    }
+}
+
+/// How to search for the best logistic regression parameters when
+/// training.  This is loaded from `recommend.core.search`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SearchConfig {
+    #[serde(default)]
+    pub method: SearchMethod,
+    /// The number of sets of parameters a random search tries.
+    #[serde(default = "defaults::search_samples")]
+    pub samples: usize,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub space: SearchSpace<f64>,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SearchMethod {
+    /// Only the parameters from `recommend.core.parameters` are used.
+    #[default]
+    None,
+    /// Every combination of the values in the search space is tried.
+    Grid,
+    /// Random combinations of the values in the search space are tried.
+    Random,
+}
+
+impl Default for SearchConfig {
+    fn default() -> SearchConfig {
+        SearchConfig {
+            method: SearchMethod::default(),
+            samples: defaults::search_samples(),
+            seed: 0,
+            space: SearchSpace::default(),
+        }
+    }
+}
+
+impl SearchConfig {
+    /// The parameters to try, starting from the given ones.
+    pub fn candidates(&self, base: Parameters<f64>) -> Vec<Parameters<f64>> {
+        match self.method {
+            SearchMethod::None => vec![base],
+            SearchMethod::Grid => self.space.grid(base),
+            SearchMethod::Random => {
+                let mut rng = StdRng::seed_from_u64(self.seed);
+                self.space.random(base, self.samples.max(1), &mut rng)
+            }
+        }
+    }
+}
+
+/// How many of the versions of each part's model are kept.  This is loaded
+/// from `recommend.core.versions`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct VersionConfig {
+    /// The number of the most recent versions that are kept.  Older
+    /// versions are deleted as new ones are trained, unless they're active.
+    #[serde(default = "defaults::retain_versions")]
+    pub retain: usize,
+}
+
+impl Default for VersionConfig {
+    fn default() -> VersionConfig {
+        VersionConfig {
+            retain: defaults::retain_versions(),
         }
     }
 }
 
 impl PartConfig {
+    /// Checks that the settings of the part make sense, and can be used
+    /// together.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(rate) = self.downsampling {
+            if !(rate > 0.0 && rate <= 1.0) {
+                return Err(format_err!("downsampling must be in (0, 1], not {}", rate));
+            }
+        }
+        if self.filter_candidate_factor == 0 {
+            return Err(format_err!("filter-candidate-factor must be at least 1"));
+        }
+
+        // The pairwise objective trains on the differences between examples,
+        // which only makes sense for a model that's linear in them.
+        if let (Learner::Factorization(_), Objective::Pairwise) = (self.learner, self.objective) {
+            return Err(format_err!(
+                "factorization machines can't be trained with the pairwise objective"
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn promotion_criterion(&self) -> Criterion {
+        self.criterion.unwrap_or_else(|| self.objective.criterion())
+    }
+
+    /// The fraction of the examples that weren't chosen that are kept for
+    /// training.  The rate is checked when the config is validated.
+    pub fn negative_rate(&self) -> f64 {
+        self.downsampling.unwrap_or(1.0)
+    }
+
+    /// The log-odds to offset the predictions of a model by, to correct for
+    /// the negative examples it wasn't trained on.
+    pub fn negative_offset(&self) -> f64 {
+        self.negative_rate().ln()
+    }
+
     pub fn extract_all(&self, list: &mut FeatureList, given: &Example, current: &Example) {
         for k in given.item.meta.keys() {
             if let Some(meta) = self.meta_features.get(k) {
@@ -39,6 +708,165 @@ impl PartConfig {
             }
         }
     }
+
+    /// Adds the crosses of the part to the features.  The crosses are only
+    /// made from the features that were already there, so crosses can't be
+    /// crossed again.
+    pub fn cross_all(&self, list: &mut FeatureList) {
+        let crossed = self
+            .crosses
+            .iter()
+            .flat_map(|cross| cross.extract(list))
+            .collect::<Vec<_>>();
+        list.extend(crossed);
+    }
+
+    /// Hashes the features, if the part is configured to.
+    pub fn hash_features(&self, list: FeatureList<'static>) -> FeatureList<'static> {
+        match self.hashing.as_ref() {
+            Some(hashing) => list.hashed(hashing.buckets, &hashing.prefix),
+            None => list,
+        }
+    }
+}
+
+/// How the items that are recommended are explored.  The policies other than
+/// `swap` use how many times each item of the part has been shown and chosen,
+/// starting from the model's prediction for it, which counts for
+/// `prior-weight` showings.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum Exploration {
+    /// With the part's `upgrade-chance`, a random item from past the items
+    /// that are recommended is swapped in for one of them.
+    #[default]
+    Swap,
+    /// Each recommended slot is, with probability `epsilon`, given to a
+    /// random item from past the items that are recommended instead.
+    EpsilonGreedy {
+        #[serde(default = "defaults::epsilon")]
+        epsilon: f64,
+    },
+    /// Items are ranked by a sample from a Beta posterior over how often
+    /// each is chosen when it's shown.
+    Thompson {
+        #[serde(default = "defaults::prior_weight")]
+        prior_weight: f64,
+    },
+    /// Items are ranked by an upper confidence bound on how often each is
+    /// chosen when it's shown, which is wider the less it has been shown.
+    Ucb {
+        #[serde(default = "defaults::prior_weight")]
+        prior_weight: f64,
+        #[serde(default = "defaults::ucb_scale")]
+        scale: f64,
+    },
+}
+
+impl Exploration {
+    /// Whether the policy uses how many times each item has been shown and
+    /// chosen.
+    pub fn uses_stats(self) -> bool {
+        match self {
+            Exploration::Swap | Exploration::EpsilonGreedy { .. } => false,
+            Exploration::Thompson { .. } | Exploration::Ucb { .. } => true,
+        }
+    }
+}
+
+/// The learning algorithm that the models of a part are trained with.
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum Learner {
+    /// Logistic regression, using the parameters from
+    /// `recommend.core.parameters`.
+    #[default]
+    Logistic,
+    /// A factorization machine, which also learns the interactions between
+    /// pairs of features.
+    Factorization(factorization::Parameters<f64>),
+}
+
+/// What the models of a part are trained to do.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Objective {
+    /// Predict whether each shown item is chosen, on its own.  Models are
+    /// evaluated by their ROC AUC over all of the examples by default.
+    #[default]
+    Pointwise,
+    /// Rank the chosen items of an activity above the ones that weren't
+    /// chosen.  Models are trained on the differences between the features
+    /// of each chosen item and each item that wasn't, and evaluated by how
+    /// many of those pairs they order correctly in each activity by default.
+    Pairwise,
+}
+
+impl Objective {
+    /// The criterion that models trained for this objective are compared
+    /// with, unless another is configured.
+    pub fn criterion(self) -> Criterion {
+        match self {
+            Objective::Pointwise => Criterion::Auc,
+            Objective::Pairwise => Criterion::PairwiseAccuracy,
+        }
+    }
+}
+
+/// A metric that a model's performance on the holdout activities is
+/// measured with, to decide whether it should replace the current model.
+/// All but the ROC AUC and the log loss are calculated for each activity,
+/// and then averaged.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum Criterion {
+    Auc,
+    PairwiseAccuracy,
+    Ndcg {
+        #[serde(default = "defaults::cutoff")]
+        k: usize,
+    },
+    Mrr,
+    Precision {
+        #[serde(default = "defaults::cutoff")]
+        k: usize,
+    },
+    Recall {
+        #[serde(default = "defaults::cutoff")]
+        k: usize,
+    },
+    LogLoss,
+}
+
+impl Criterion {
+    /// Measures the performance of the scored examples of each activity,
+    /// given as `(score, label)`.
+    pub fn evaluate(self, groups: &[Vec<(f64, f64)>]) -> f64 {
+        match self {
+            Criterion::Auc => {
+                let (hat, tru) = groups
+                    .iter()
+                    .flatten()
+                    .cloned()
+                    .unzip::<_, _, Vec<_>, Vec<_>>();
+                metrics::roc_auc_score(&tru, &hat)
+            }
+            Criterion::PairwiseAccuracy => metrics::pairwise_accuracy(groups),
+            Criterion::Ndcg { k } => metrics::ndcg_at(groups, k),
+            Criterion::Mrr => metrics::mean_reciprocal_rank(groups),
+            Criterion::Precision { k } => metrics::precision_at(groups, k),
+            Criterion::Recall { k } => metrics::recall_at(groups, k),
+            Criterion::LogLoss => metrics::log_loss(groups),
+        }
+    }
+
+    /// Whether the new performance is an improvement over the old one.
+    pub fn improved(self, old: f64, new: f64) -> bool {
+        match self {
+            Criterion::LogLoss => new < old,
+            _ => new > old,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,6 +874,10 @@ impl PartConfig {
 pub enum MetaFeature {
     Ignore,
     Overlap,
+    /// A feature for each of the item's values of the meta, named
+    /// `meta:<name>:value:<value>`.  Meta with many distinct values should
+    /// be used with hashing.
+    Values,
 }
 
 impl Default for MetaFeature {
@@ -66,6 +898,208 @@ impl MetaFeature {
 
                 list.insert(format!("meta:{}:overlap", name), overlap as f64);
             }
+            MetaFeature::Values => {
+                for value in given.item.meta.get(name).into_iter().flatten() {
+                    list.insert(format!("meta:{}:value:{}", name, value), 1.0);
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_crosses_features() {
+        let mut list = vec![
+            ("list:near:rank", 2.0),
+            ("list:top:hour:rank", 3.0),
+            ("list:top:hour:value:ln1p", 0.0),
+            ("list:top:hours", 5.0),
+        ]
+        .into_iter()
+        .collect::<FeatureList>();
+        let config = PartConfig {
+            crosses: vec![
+                Cross {
+                    features: vec!["list:near:rank".into(), "list:top:hour".into()],
+                    kind: CrossKind::Product,
+                },
+                Cross {
+                    features: vec!["list:near:rank".into(), "list:top:hour".into()],
+                    kind: CrossKind::Conjunction,
+                },
+            ],
+            ..Default::default()
+        };
+        config.cross_all(&mut list);
+
+        assert_eq!(list.len(), 8);
+        assert_eq!(list["cross:list:near:rank*list:top:hour:rank"], 6.0);
+        assert_eq!(list["cross:list:near:rank*list:top:hour:value:ln1p"], 0.0);
+        assert_eq!(list["cross:list:near:rank&list:top:hour:rank"], 1.0);
+        assert_eq!(list["cross:list:near:rank&list:top:hour:value:ln1p"], 0.0);
+    }
+
+    #[test]
+    fn it_standardizes_features() {
+        let lists = vec![
+            vec![("list:near:rank", 1.0), ("meta:tag:overlap", 4.0)],
+            vec![("list:near:rank", 3.0)],
+        ]
+        .into_iter()
+        .map(|list| list.into_iter().collect::<FeatureList>())
+        .collect::<Vec<_>>();
+
+        let scaling = Standardization::ZScore.fit(lists.iter());
+        assert_eq!(scaling["list:near:rank"].shift, 2.0);
+        assert_eq!(scaling["list:near:rank"].scale, 1.0);
+        assert_eq!(scaling["list:near:rank"].apply(3.0), 1.0);
+        // A feature that only has one value is left as it is.
+        assert!(!scaling.contains_key("meta:tag:overlap"));
+
+        let scaling = Standardization::MinMax.fit(lists.iter());
+        assert_eq!(scaling["list:near:rank"].apply(1.0), 0.0);
+        assert_eq!(scaling["list:near:rank"].apply(3.0), 1.0);
+    }
+
+    #[test]
+    fn it_validates_part_configs() {
+        let config = PartConfig {
+            learner: Learner::Factorization(Default::default()),
+            objective: Objective::Pairwise,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = PartConfig {
+            objective: Objective::Pairwise,
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        for rate in &[f64::NAN, f64::INFINITY, 0.0, -0.5, 1.5] {
+            let config = PartConfig {
+                downsampling: Some(*rate),
+                ..Default::default()
+            };
+            assert!(config.validate().is_err());
+        }
+        let config = PartConfig {
+            downsampling: Some(0.1),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let config = PartConfig {
+            filter_candidate_factor: 0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn it_guards_promotion() {
+        let model = |weights: Vec<(&'static str, f64)>| {
+            Model::linear(weights.into_iter().collect::<FeatureList>())
+        };
+        let report = TrainReport {
+            examples: 100,
+            training: 70,
+            holdout: 30,
+            positives: 3,
+            folds: None,
+            candidates: 1,
+            criterion: Criterion::Auc,
+            old_performance: 0.6,
+            new_performance: 0.7,
+            improved: true,
+            rejection: None,
+            promoted: false,
+            version: Default::default(),
+            convergence: Default::default(),
+        };
+        let current = model(vec![("list:near:rank", 2.0)]);
+        let trained = model(vec![("list:near:rank", 3.0), ("meta:tag:overlap", 0.5)]);
+        let guards = PromotionGuards {
+            min_holdout: 30,
+            min_positives: 3,
+            min_performance: Some(0.65),
+            max_weight: Some(3.0),
+            max_change: Some(0.5),
+        };
+        assert_eq!(guards.check(&report, &current, &trained), None);
+
+        let broken = model(vec![("list:near:rank", f64::NAN)]);
+        assert_eq!(
+            PromotionGuards::default().check(&report, &current, &broken),
+            Some(Rejection::NonFinite {
+                feature: "list:near:rank".into()
+            })
+        );
+
+        let strict = PromotionGuards {
+            min_positives: 4,
+            ..guards.clone()
+        };
+        assert!(matches!(
+            strict.check(&report, &current, &trained),
+            Some(Rejection::FewPositives { .. })
+        ));
+
+        let strict = PromotionGuards {
+            min_performance: Some(0.75),
+            ..guards.clone()
+        };
+        assert!(matches!(
+            strict.check(&report, &current, &trained),
+            Some(Rejection::LowPerformance { .. })
+        ));
+
+        let strict = PromotionGuards {
+            max_change: Some(0.25),
+            ..guards
+        };
+        assert!(matches!(
+            strict.check(&report, &current, &trained),
+            Some(Rejection::LargeChange { .. })
+        ));
+    }
+
+    #[test]
+    fn it_weighs_by_inverse_propensity() {
+        let activity = Activity {
+            id: Default::default(),
+            part: "default".into(),
+            current: Example::new(
+                crate::storage::BasicExample::new(Default::default()),
+                crate::storage::Item::with_id(Default::default()),
+            ),
+            visible: vec![],
+            chosen: None,
+            event: None,
+            created: 0,
+            impressions: vec![],
+            learned: false,
+        };
+        let impression = |propensity| Impression {
+            position: 0,
+            score: 0.5,
+            propensity,
+        };
+        let weighting = WeightingConfig {
+            max_inverse_propensity: Some(10.0),
+            ..Default::default()
+        };
+
+        assert_eq!(weighting.weight(&activity, None, true, 0), 1.0);
+        let rare = impression(0.25);
+        assert_eq!(weighting.weight(&activity, Some(&rare), true, 0), 4.0);
+        let rarer = impression(0.01);
+        assert_eq!(weighting.weight(&activity, Some(&rarer), false, 0), 10.0);
+        let plain = WeightingConfig::default();
+        assert_eq!(plain.weight(&activity, Some(&rare), true, 0), 1.0);
+    }
+}
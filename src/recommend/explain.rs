@@ -0,0 +1,66 @@
+use crate::recommend::PartConfig;
+use crate::storage::{Example, Model};
+use std::cmp::Ordering;
+use uuid::Uuid;
+
+/// The number of features that are given in each explanation.
+const TOP_FEATURES: usize = 10;
+
+/// Why an item was recommended: the candidate sources it came from, and the
+/// features that contributed the most to its score.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Explanation {
+    pub item: Uuid,
+    /// The lists the item was a candidate from, such as `near`, `recent`,
+    /// `top:day`, or `pop:hour`.  This is empty if the item was whitelisted.
+    pub sources: Vec<String>,
+    /// The features that contributed the most to the score, largest in
+    /// magnitude first.
+    pub features: Vec<Contribution>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Contribution {
+    pub feature: String,
+    pub value: f64,
+    pub weight: f64,
+    /// The value multiplied by the weight.
+    pub contribution: f64,
+}
+
+/// Explains the score of the example.  Only the linear weights of the model
+/// are explained; for factorization machines, the interactions between
+/// features are left out.
+pub(crate) fn explain(
+    example: &Example,
+    current: &Example,
+    model: &Model,
+    config: &PartConfig,
+) -> Explanation {
+    let features = model.scale(example.features(current, config));
+    let mut features = features
+        .combine(&model.weights)
+        .map(|(feature, value, weight)| Contribution {
+            feature: feature.to_owned(),
+            value,
+            weight,
+            contribution: value * weight,
+        })
+        .filter(|c| c.contribution != 0.0)
+        .collect::<Vec<_>>();
+    features.sort_by(|a, b| {
+        b.contribution
+            .abs()
+            .partial_cmp(&a.contribution.abs())
+            .unwrap_or(Ordering::Equal)
+    });
+    features.truncate(TOP_FEATURES);
+
+    Explanation {
+        item: example.item.id,
+        sources: example.basic.sources(),
+        features,
+    }
+}
@@ -0,0 +1,270 @@
+use crate::recommend::{Exploration, PartConfig};
+use crate::storage::{Example, ItemStats, Model};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Beta, Distribution};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// The number of times the rankings are sampled again to estimate the
+/// propensities of Thompson sampling, which have no closed form.
+const SIMULATIONS: usize = 100;
+
+/// The number of examples past the ones that are shown that are sampled
+/// again in the simulations.  The examples further down hardly ever make it
+/// into the shown ones, so they're left out to keep the simulations cheap.
+const SIMULATION_TAIL: usize = 20;
+
+/// Reorders the scored examples, which are sorted by score, by the part's
+/// exploration policy.  Only the first `count` of them are recommended.
+/// Returns the probability that the policy put each of the examples at its
+/// position; this is only meaningful for the first `count`.
+pub(crate) fn explore(
+    scored: &mut Vec<(Example, f64)>,
+    count: usize,
+    config: &PartConfig,
+    model: &Model,
+    stats: &HashMap<Uuid, ItemStats>,
+) -> Vec<f64> {
+    let mut rng = match config.exploration_seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    match config.exploration {
+        Exploration::Swap => swap(scored, count, config.upgrade_chance, &mut rng),
+        Exploration::EpsilonGreedy { epsilon } => epsilon_greedy(scored, count, epsilon, &mut rng),
+        Exploration::Thompson { prior_weight } => {
+            let posteriors = scored
+                .iter()
+                .map(|(example, score)| posterior(model, stats, example, *score, prior_weight))
+                .collect::<Vec<_>>();
+            // Ranks the examples at the given indices by a sample of each of
+            // their posteriors.
+            let sample = |indices: &[usize], rng: &mut StdRng| {
+                let keys = indices
+                    .iter()
+                    .map(|i| sample_beta(posteriors[*i].0, posteriors[*i].1, rng))
+                    .collect::<Vec<_>>();
+                rank_by(&keys)
+                    .into_iter()
+                    .map(|k| indices[k])
+                    .collect::<Vec<_>>()
+            };
+
+            let order = sample(&(0..posteriors.len()).collect::<Vec<_>>(), &mut rng);
+            let shown = count.min(order.len());
+            let contenders = &order[..(shown + SIMULATION_TAIL).min(order.len())];
+            let mut hits = vec![0; shown];
+            for _ in 0..SIMULATIONS {
+                let again = sample(contenders, &mut rng);
+                for (position, hit) in hits.iter_mut().enumerate() {
+                    if again[position] == order[position] {
+                        *hit += 1;
+                    }
+                }
+            }
+
+            permute(scored, &order);
+            let mut propensities = vec![1.0; scored.len()];
+            for (propensity, hit) in propensities.iter_mut().zip(hits) {
+                *propensity = (hit + 1) as f64 / (SIMULATIONS + 1) as f64;
+            }
+            propensities
+        }
+        Exploration::Ucb {
+            prior_weight,
+            scale,
+        } => {
+            let total = stats.values().map(|s| s.shown).sum::<u64>() as f64;
+            let keys = scored
+                .iter()
+                .map(|(example, score)| {
+                    let (chosen, missed) = posterior(model, stats, example, *score, prior_weight);
+                    let shown = chosen + missed;
+                    chosen / shown + scale * ((total + 1.0).ln() / shown.max(1.0)).sqrt()
+                })
+                .collect::<Vec<_>>();
+            permute(scored, &rank_by(&keys));
+            vec![1.0; scored.len()]
+        }
+    }
+}
+
+/// With the given chance, swaps a random example from past the first `count`
+/// in for one of them.
+fn swap(scored: &mut [(Example, f64)], count: usize, chance: f64, rng: &mut impl Rng) -> Vec<f64> {
+    let mut propensities = vec![1.0; scored.len()];
+    if count >= scored.len() {
+        return propensities;
+    }
+
+    // Each of the slots keeps its example unless it's the one swapped into.
+    let chance = chance.clamp(0.0, 1.0);
+    for propensity in propensities.iter_mut().take(count) {
+        *propensity = 1.0 - chance / count as f64;
+    }
+    if !rng.gen_bool(chance) {
+        return propensities;
+    }
+
+    let from = rng.gen_range(count, scored.len());
+    let to = rng.gen_range(0, count);
+
+    scored.swap(to, from);
+    propensities[to] = chance / (count * (scored.len() - count)) as f64;
+    propensities
+}
+
+/// Gives each of the first `count` slots, with probability `epsilon`, to a
+/// random example from past them.
+fn epsilon_greedy(
+    scored: &mut [(Example, f64)],
+    count: usize,
+    epsilon: f64,
+    rng: &mut impl Rng,
+) -> Vec<f64> {
+    let mut propensities = vec![1.0; scored.len()];
+    if count >= scored.len() {
+        return propensities;
+    }
+
+    // The examples that are swapped out of a slot aren't swapped back in.
+    let epsilon = epsilon.clamp(0.0, 1.0);
+    let mut pool = (count..scored.len()).collect::<Vec<_>>();
+    for (slot, propensity) in propensities.iter_mut().enumerate().take(count) {
+        if !pool.is_empty() && rng.gen_bool(epsilon) {
+            *propensity = epsilon / pool.len() as f64;
+            let from = pool.swap_remove(rng.gen_range(0, pool.len()));
+            scored.swap(slot, from);
+        } else {
+            *propensity = 1.0 - epsilon;
+        }
+    }
+    propensities
+}
+
+/// The indices of the keys, from the largest key to the smallest.
+fn rank_by(keys: &[f64]) -> Vec<usize> {
+    let mut order = (0..keys.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| keys[*b].partial_cmp(&keys[*a]).unwrap_or(Ordering::Equal));
+    order
+}
+
+/// Reorders the examples so that the example at each position is the one
+/// that was at the index given for that position.
+fn permute(scored: &mut Vec<(Example, f64)>, order: &[usize]) {
+    let mut examples = scored.drain(..).map(Some).collect::<Vec<_>>();
+    scored.extend(order.iter().flat_map(|i| examples[*i].take()));
+}
+
+/// The parameters of the Beta posterior over how often the example's item is
+/// chosen when it's shown: the number of times it was chosen, and the number
+/// of times it wasn't.  The model's prediction counts for `prior_weight`
+/// showings.
+fn posterior(
+    model: &Model,
+    stats: &HashMap<Uuid, ItemStats>,
+    example: &Example,
+    score: f64,
+    prior_weight: f64,
+) -> (f64, f64) {
+    let prior = model.correct(score).clamp(f64::EPSILON, 1.0 - f64::EPSILON);
+    let stats = stats.get(&example.item.id).cloned().unwrap_or_default();
+    let missed = stats.shown.saturating_sub(stats.chosen);
+    let weight = prior_weight.max(f64::EPSILON);
+    (
+        weight * prior + stats.chosen as f64,
+        weight * (1.0 - prior) + missed as f64,
+    )
+}
+
+/// Samples from a Beta distribution.  The posteriors' parameters are always
+/// positive, but if one isn't, the sample falls back to the mean.
+fn sample_beta(alpha: f64, beta: f64, rng: &mut impl Rng) -> f64 {
+    match Beta::new(alpha, beta) {
+        Ok(distribution) => distribution.sample(rng),
+        Err(_) => alpha / (alpha + beta),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{BasicExample, Item};
+
+    fn examples(count: usize) -> Vec<(Example, f64)> {
+        (0..count)
+            .map(|i| {
+                let id = Uuid::new_v4();
+                let score = 0.5 - i as f64 * 0.01;
+                (
+                    Example::new(BasicExample::new(id), Item::with_id(id)),
+                    score,
+                )
+            })
+            .collect()
+    }
+
+    fn ids(scored: &[(Example, f64)]) -> Vec<Uuid> {
+        scored.iter().map(|(e, _)| e.item.id).collect()
+    }
+
+    #[test]
+    fn it_explores_recommendations() {
+        let model = Model::default();
+        let scored = examples(10);
+        let config = |exploration| PartConfig {
+            exploration,
+            exploration_seed: Some(7),
+            ..Default::default()
+        };
+        let explored = |config: &PartConfig, stats: &HashMap<Uuid, ItemStats>| {
+            let mut list = scored.clone();
+            let propensities = explore(&mut list, 3, config, &model, stats);
+            assert_eq!(propensities.len(), list.len());
+            assert!(propensities.iter().all(|p| *p > 0.0 && *p <= 1.0));
+            ids(&list)
+        };
+        let none = HashMap::new();
+
+        let greedy = config(Exploration::EpsilonGreedy { epsilon: 0.0 });
+        assert_eq!(explored(&greedy, &none), ids(&scored));
+        let random = config(Exploration::EpsilonGreedy { epsilon: 1.0 });
+        let list = explored(&random, &none);
+        assert!(list[..3].iter().all(|id| !ids(&scored)[..3].contains(id)));
+        // The same seed explores the same way every time.
+        assert_eq!(explored(&random, &none), list);
+
+        // An item that has been chosen every time it was shown is ranked
+        // first, and one that never was is ranked last.
+        let mut stats = HashMap::new();
+        let (best, worst) = (scored[9].0.item.id, scored[0].0.item.id);
+        stats.insert(
+            best,
+            ItemStats {
+                shown: 500,
+                chosen: 500,
+            },
+        );
+        stats.insert(
+            worst,
+            ItemStats {
+                shown: 500,
+                chosen: 0,
+            },
+        );
+        for exploration in &[
+            Exploration::Thompson { prior_weight: 10.0 },
+            Exploration::Ucb {
+                prior_weight: 10.0,
+                scale: 0.1,
+            },
+        ] {
+            let list = explored(&config(*exploration), &stats);
+            assert_eq!(list[0], best);
+            assert_eq!(list[9], worst);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use crate::storage::Item;
+
+/// An expression over the meta of an item, which decides whether the item
+/// can be recommended.  For example, only shoes that aren't from brand X:
+///
+/// ```json
+/// { "type": "and", "filters": [
+///     { "type": "equals", "key": "category", "value": "shoes" },
+///     { "type": "not-in", "key": "brand", "values": ["X"] }
+/// ] }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum Filter {
+    /// The item has the value for the meta.
+    Equals {
+        key: String,
+        value: String,
+    },
+    /// The item has at least one of the values for the meta.
+    In {
+        key: String,
+        values: Vec<String>,
+    },
+    /// The item has none of the values for the meta.  Items without the meta
+    /// match.
+    NotIn {
+        key: String,
+        values: Vec<String>,
+    },
+    /// The item has at least one value for the meta.
+    HasKey {
+        key: String,
+    },
+    /// Every one of the filters matches.
+    And {
+        filters: Vec<Filter>,
+    },
+    /// At least one of the filters matches.
+    Or {
+        filters: Vec<Filter>,
+    },
+    Not {
+        filter: Box<Filter>,
+    },
+}
+
+impl Filter {
+    pub fn matches(&self, item: &Item) -> bool {
+        let values = |key: &str| item.meta.get(key).into_iter().flatten();
+        match self {
+            Filter::Equals { key, value } => values(key).any(|v| v == value),
+            Filter::In { key, values: any } => values(key).any(|v| any.contains(v)),
+            Filter::NotIn { key, values: none } => !values(key).any(|v| none.contains(v)),
+            Filter::HasKey { key } => values(key).next().is_some(),
+            Filter::And { filters } => filters.iter().all(|f| f.matches(item)),
+            Filter::Or { filters } => filters.iter().any(|f| f.matches(item)),
+            Filter::Not { filter } => !filter.matches(item),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[test]
+    fn it_filters_items() {
+        let item = |meta: &[(&str, &str)]| {
+            let mut item = Item::with_id(Uuid::new_v4());
+            for (key, value) in meta {
+                item.meta
+                    .entry(key.to_string())
+                    .or_default()
+                    .insert(value.to_string());
+            }
+            item
+        };
+        let filter = serde_json::from_value::<Filter>(json!({
+            "type": "and",
+            "filters": [
+                { "type": "equals", "key": "category", "value": "shoes" },
+                { "type": "not-in", "key": "brand", "values": ["x"] },
+                { "type": "or", "filters": [
+                    { "type": "has-key", "key": "stock" },
+                    { "type": "not", "filter": { "type": "in", "key": "kind", "values": ["a", "b"] } }
+                ] }
+            ]
+        }))
+        .unwrap();
+
+        assert!(filter.matches(&item(&[("category", "shoes"), ("stock", "3")])));
+        assert!(filter.matches(&item(&[("category", "shoes"), ("brand", "y")])));
+        assert!(filter.matches(&item(&[("category", "hats"), ("category", "shoes")])));
+        assert!(!filter.matches(&item(&[("category", "hats")])));
+        assert!(!filter.matches(&item(&[("category", "shoes"), ("brand", "x")])));
+        assert!(!filter.matches(&item(&[("category", "shoes"), ("kind", "b")])));
+    }
+}
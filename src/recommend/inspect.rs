@@ -0,0 +1,109 @@
+use crate::storage::Model;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// A readable view of a model: its weights grouped by the prefix of their
+/// features, with the largest weights first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Inspection {
+    pub offset: f64,
+    pub groups: Vec<FeatureGroup>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FeatureGroup {
+    /// The prefix of the features in the group, such as `list:near`,
+    /// `list:top`, or `meta:category`.
+    pub prefix: String,
+    pub features: Vec<FeatureWeight>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FeatureWeight {
+    pub feature: String,
+    pub weight: f64,
+    /// The fraction of the examples the model was last trained on that had
+    /// the feature (with a non-zero value), if it's known.
+    pub coverage: Option<f64>,
+}
+
+/// How the weight of a feature changed between two models.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WeightChange {
+    pub feature: String,
+    pub from: Option<f64>,
+    pub to: Option<f64>,
+    pub change: f64,
+}
+
+impl Inspection {
+    pub fn of(model: &Model) -> Inspection {
+        let mut groups = BTreeMap::<String, Vec<FeatureWeight>>::new();
+        for (feature, weight) in model.weights.iter() {
+            groups
+                .entry(prefix_of(feature))
+                .or_default()
+                .push(FeatureWeight {
+                    feature: feature.to_string(),
+                    weight: *weight,
+                    coverage: model.coverage.get(feature.as_ref()).cloned(),
+                });
+        }
+
+        let groups = groups
+            .into_iter()
+            .map(|(prefix, mut features)| {
+                features.sort_by(|a, b| by_magnitude(a.weight, b.weight));
+                FeatureGroup { prefix, features }
+            })
+            .collect();
+
+        Inspection {
+            offset: model.offset,
+            groups,
+        }
+    }
+}
+
+/// The changes in the weights from one model to the other, largest first.
+/// Features that are only in one of the models are included, with their
+/// weight in the other missing.
+pub fn diff(from: &Model, to: &Model) -> Vec<WeightChange> {
+    let mut changes = from
+        .weights
+        .union(&to.weights)
+        .map(|(feature, _, _)| {
+            let (old, new) = (
+                from.weights.get(feature.as_ref()).cloned(),
+                to.weights.get(feature.as_ref()).cloned(),
+            );
+            WeightChange {
+                feature: feature.into_owned(),
+                from: old,
+                to: new,
+                change: new.unwrap_or(0.0) - old.unwrap_or(0.0),
+            }
+        })
+        .collect::<Vec<_>>();
+    changes.sort_by(|a, b| by_magnitude(a.change, b.change));
+    changes
+}
+
+/// The group a feature belongs to.  This is the first two parts of its key,
+/// except for crossed and hashed features, which are each grouped together.
+fn prefix_of(feature: &str) -> String {
+    let mut parts = feature.splitn(3, ':');
+    match (parts.next(), parts.next()) {
+        (Some(kind @ "cross"), _) | (Some(kind @ "hash"), _) => kind.to_owned(),
+        (Some(kind), Some(name)) => format!("{}:{}", kind, name),
+        _ => feature.to_owned(),
+    }
+}
+
+fn by_magnitude(a: f64, b: f64) -> Ordering {
+    b.abs().partial_cmp(&a.abs()).unwrap_or(Ordering::Equal)
+}
@@ -0,0 +1,213 @@
+use crate::recommend::{Core, TrainOutcome};
+use crate::storage::Store;
+use failure::Error;
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use uuid::Uuid;
+
+/// The number of training jobs that are remembered.  Once there are more
+/// jobs than this, the oldest ones that are done are forgotten; jobs that
+/// are still waiting or running are always kept.
+const JOB_HISTORY_LENGTH: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+    /// The job ran, but there weren't enough examples to train on.
+    Skipped,
+    Failed,
+}
+
+impl JobStatus {
+    /// Whether the job has stopped, one way or another.
+    pub fn is_done(self) -> bool {
+        match self {
+            JobStatus::Queued | JobStatus::Running => false,
+            JobStatus::Finished | JobStatus::Skipped | JobStatus::Failed => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TrainJob {
+    pub id: Uuid,
+    pub part: String,
+    pub status: JobStatus,
+    pub outcome: Option<TrainOutcome>,
+    pub error: Option<String>,
+}
+
+impl TrainJob {
+    fn new(part: &str) -> TrainJob {
+        TrainJob {
+            id: Uuid::new_v4(),
+            part: part.to_owned(),
+            status: JobStatus::Queued,
+            outcome: None,
+            error: None,
+        }
+    }
+
+    fn complete(&mut self, result: Result<TrainOutcome, Error>) {
+        match result {
+            Ok(outcome) => {
+                self.status = match outcome {
+                    TrainOutcome::Skipped { .. } => JobStatus::Skipped,
+                    TrainOutcome::Trained(_) => JobStatus::Finished,
+                };
+                self.outcome = Some(outcome);
+            }
+            Err(e) => {
+                self.status = JobStatus::Failed;
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+/// Keeps track of the training jobs that have been requested, and makes sure
+/// that only one training run happens at a time.  Requested jobs are queued
+/// for a single worker thread, which is started with the first job.
+#[derive(Debug, Default)]
+pub struct TrainJobs {
+    lock: Mutex<()>,
+    jobs: Mutex<JobList>,
+    queue: Mutex<Option<Sender<Uuid>>>,
+}
+
+#[derive(Debug, Default)]
+struct JobList {
+    order: VecDeque<Uuid>,
+    map: HashMap<Uuid, TrainJob>,
+}
+
+impl TrainJobs {
+    pub fn find(&self, id: Uuid) -> Option<TrainJob> {
+        self.list().map.get(&id).cloned()
+    }
+
+    /// Blocks until no other training run is happening.  Training runs
+    /// should hold on to the returned guard for as long as they run.
+    pub fn exclusive(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds a job for the part, unless there's already one waiting to run,
+    /// which is returned instead.  Returns whether the job was added.
+    fn insert(&self, part: &str) -> (TrainJob, bool) {
+        let mut list = self.list();
+        let queued = list
+            .map
+            .values()
+            .find(|job| job.part == part && job.status == JobStatus::Queued);
+        if let Some(job) = queued {
+            return (job.clone(), false);
+        }
+
+        let job = TrainJob::new(part);
+        list.order.push_back(job.id);
+        list.map.insert(job.id, job.clone());
+        while list.order.len() > JOB_HISTORY_LENGTH {
+            let JobList { order, map } = &mut *list;
+            match order.iter().position(|id| map[id].status.is_done()) {
+                Some(index) => {
+                    let id = order.remove(index).unwrap();
+                    map.remove(&id);
+                }
+                None => break,
+            }
+        }
+        (job, true)
+    }
+
+    fn update(&self, id: Uuid, f: impl FnOnce(&mut TrainJob)) {
+        if let Some(job) = self.list().map.get_mut(&id) {
+            f(job);
+        }
+    }
+
+    fn list(&self) -> MutexGuard<'_, JobList> {
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn queue(&self) -> MutexGuard<'_, Option<Sender<Uuid>>> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Store + Send + Sync + 'static> Core<T> {
+    /// Queues up a training run for the given part in the background,
+    /// returning the job immediately.  If a run for the part is already
+    /// queued, that job is returned instead.  The job can be checked on
+    /// later using [`TrainJobs::find`].
+    pub fn train_job(core: &Arc<Self>, part: &str) -> TrainJob {
+        let (job, added) = core.jobs.insert(part);
+        if !added {
+            return job;
+        }
+
+        let mut queue = core.jobs.queue();
+        let sent = queue
+            .as_ref()
+            .map(|sender| sender.send(job.id).is_ok())
+            .unwrap_or(false);
+        if !sent {
+            // The worker hasn't been started yet, or it stopped.
+            let sender = Core::train_worker(core);
+            let _ = sender.send(job.id);
+            *queue = Some(sender);
+        }
+        job
+    }
+
+    /// Starts the thread that runs the queued training jobs, one at a time.
+    fn train_worker(core: &Arc<Self>) -> Sender<Uuid> {
+        let (sender, receiver) = mpsc::channel::<Uuid>();
+        let core = core.clone();
+        std::thread::spawn(move || {
+            for id in receiver {
+                let part = match core.jobs.find(id) {
+                    Some(job) => job.part,
+                    None => continue,
+                };
+                let _guard = core.jobs.exclusive();
+                core.jobs.update(id, |job| job.status = JobStatus::Running);
+                let result = core.load_train_part(&part);
+                if let Err(e) = result.as_ref() {
+                    error!("error occurred during train job {}: {:?}", id, e);
+                }
+                core.jobs.update(id, |job| job.complete(result));
+            }
+        });
+        sender
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_only_forgets_jobs_that_are_done() {
+        let jobs = TrainJobs::default();
+        let waiting = (0..JOB_HISTORY_LENGTH + 8)
+            .map(|i| jobs.insert(&format!("part-{}", i)).0)
+            .collect::<Vec<_>>();
+        assert!(waiting.iter().all(|job| jobs.find(job.id).is_some()));
+
+        for job in &waiting[..16] {
+            jobs.update(job.id, |job| job.status = JobStatus::Finished);
+        }
+        let (latest, added) = jobs.insert("latest");
+        assert!(added);
+        assert!(jobs.find(latest.id).is_some());
+        assert!(jobs.find(waiting[8].id).is_none());
+        assert!(jobs.find(waiting[9].id).is_some());
+        assert!(waiting[16..].iter().all(|job| jobs.find(job.id).is_some()));
+    }
+}
@@ -1,10 +1,21 @@
-pub use self::conf::PartConfig;
+pub use self::conf::{
+    CalibrationMethod, Criterion, Cross, CrossKind, Exploration, HashingConfig, Learner,
+    MetaFeature, Objective, PartConfig, PromotionGuards, SearchConfig, SearchMethod,
+    Standardization, ValidationConfig, VersionConfig, WeightingConfig,
+};
+pub use self::explain::{Contribution, Explanation};
+pub use self::filter::Filter;
+pub use self::inspect::{diff, FeatureGroup, FeatureWeight, Inspection, WeightChange};
+pub use self::jobs::{JobStatus, TrainJob, TrainJobs};
+pub use self::online::OnlineModels;
+pub use self::replay::{replay, Candidate, CandidateReport, ReplayReport};
 pub use self::request::Request;
+pub use self::train::{Rejection, TrainOutcome, TrainReport};
+pub use self::versions::DEFAULT_PART;
 use crate::learn::logistic::Parameters;
-use crate::storage::{Activity, BasicExample, Example, FeatureList, Store};
+use crate::storage::{Activity, BasicExample, Example, Impression, Model, Store};
 use config::Config;
-use failure::Error;
-use rand::Rng;
+use failure::{format_err, Error};
 use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::hash::Hash;
@@ -13,8 +24,16 @@ use std::time::Duration;
 use uuid::Uuid;
 
 mod conf;
+mod explain;
+mod explore;
+mod filter;
+mod inspect;
+mod jobs;
+mod online;
+mod replay;
 mod request;
 mod train;
+mod versions;
 
 #[derive(Debug, Clone)]
 pub struct Core<T: Store + 'static> {
@@ -22,19 +41,42 @@ pub struct Core<T: Store + 'static> {
     pub parameters: Parameters<f64>,
     pub part_config: HashMap<String, PartConfig>,
     pub default_config: PartConfig,
+    pub validation: ValidationConfig,
+    pub search: SearchConfig,
+    pub versions: VersionConfig,
+    pub jobs: Arc<TrainJobs>,
+    pub online: Arc<OnlineModels>,
 }
 
 impl<T: Store + 'static> Core<T> {
-    pub fn of(storage: &Arc<T>, config: &Config) -> Core<T> {
-        let default_config = config.get("recommend.core.default").unwrap_or_default();
-        let part_config = config.get("recommend.core.parts").unwrap_or_default();
+    /// Loads the core from the `recommend.core` configuration.  Fails if the
+    /// configuration of any part isn't valid.
+    pub fn of(storage: &Arc<T>, config: &Config) -> Result<Core<T>, Error> {
+        let default_config: PartConfig = config.get("recommend.core.default").unwrap_or_default();
+        let part_config: HashMap<String, PartConfig> =
+            config.get("recommend.core.parts").unwrap_or_default();
+        default_config
+            .validate()
+            .map_err(|e| format_err!("default part: {}", e))?;
+        for (name, part) in part_config.iter() {
+            part.validate()
+                .map_err(|e| format_err!("part {}: {}", name, e))?;
+        }
         let parameters = config.get("recommend.core.parameters").unwrap_or_default();
-        Core {
+        let validation = config.get("recommend.core.validation").unwrap_or_default();
+        let search = config.get("recommend.core.search").unwrap_or_default();
+        let versions = config.get("recommend.core.versions").unwrap_or_default();
+        Ok(Core {
             storage: storage.clone(),
             parameters,
             part_config,
             default_config,
-        }
+            validation,
+            search,
+            versions,
+            jobs: Default::default(),
+            online: Default::default(),
+        })
     }
 }
 
@@ -42,13 +84,17 @@ impl<T: Store + 'static> Core<T> {
 pub struct Response {
     pub items: Vec<(Uuid, f64)>,
     pub id: Uuid,
+    /// Why each of the items was recommended, in the same order as the
+    /// items.  This is only given if the request asked for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanations: Option<Vec<Explanation>>,
 }
 
 impl<T: Store + 'static> Core<T> {
     pub fn config_for<Q>(&self, name: &Q) -> &PartConfig
     where
         String: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
     {
         self.part_config
             .get(name)
@@ -61,23 +107,64 @@ impl<T: Store + 'static> Core<T> {
         let current = Example::new(BasicExample::new(current_item.id), current_item);
         let config = self.config_for(&request.part);
         debug!("config={:?}", config);
-        let model = pluck_model(self.storage.as_ref(), &request.part)?;
+        let model = match self.online.find(&request.part) {
+            Some(model) => model,
+            None => Arc::new(pluck_model(self.storage.as_ref(), &request.part)?),
+        };
         debug!("model={:?}", model);
         let examples = request.examples(self)?;
         debug!("examples=impl");
         let mut scored = score_examples(examples, &current, &model, config).collect::<Vec<_>>();
         debug!("scored={:?}", scored);
         crate::ord::sort_float(&mut scored, |(_, a)| *a);
-        resort_examples(&mut scored, request.count, config);
+        let stats = if config.exploration.uses_stats() {
+            self.storage.model_stats_find(&request.part)?
+        } else {
+            HashMap::new()
+        };
+        let mut propensities = explore::explore(&mut scored, request.count, config, &model, &stats);
         scored.truncate(request.count);
+        propensities.truncate(request.count);
         debug!("scored.truncate");
 
-        let id = build_activity(self.storage.as_ref(), request, current, &scored[..])?;
+        let explanations = if request.explain {
+            let list = scored
+                .iter()
+                .map(|(example, _)| explain::explain(example, &current, &model, config))
+                .collect();
+            Some(list)
+        } else {
+            None
+        };
+
+        let impressions = scored
+            .iter()
+            .zip(propensities)
+            .enumerate()
+            .map(|(position, ((_, score), propensity))| Impression {
+                position,
+                score: model.correct(*score),
+                propensity,
+            })
+            .collect();
+        let id = build_activity(
+            self.storage.as_ref(),
+            request,
+            config,
+            current,
+            &scored[..],
+            impressions,
+        )?;
         debug!("id={:?}", id);
 
+        let items = scored
+            .into_iter()
+            .map(|(v, s)| (v.item.id, model.correct(s)))
+            .collect();
         Ok(Response {
-            items: scored.into_iter().map(|(v, s)| (v.item.id, s)).collect(),
+            items,
             id,
+            explanations,
         })
     }
 }
@@ -88,6 +175,7 @@ impl<T: Store + Send + Sync + 'static> Core<T> {
         std::thread::spawn(move || loop {
             std::thread::sleep(Duration::from_secs(60 * 10));
             info!("performing load_train...");
+            let _guard = core.jobs.exclusive();
             match core.load_train() {
                 Ok(_) => info!("load_train successful!"),
                 Err(e) => {
@@ -98,7 +186,7 @@ impl<T: Store + Send + Sync + 'static> Core<T> {
     }
 }
 
-fn pluck_model<T: Store>(storage: &T, part: &str) -> Result<FeatureList<'static>, Error> {
+pub(crate) fn pluck_model<T: Store>(storage: &T, part: &str) -> Result<Model, Error> {
     let model = storage.find_model(part)?;
     match model {
         Some(model) => Ok(model),
@@ -109,17 +197,27 @@ fn pluck_model<T: Store>(storage: &T, part: &str) -> Result<FeatureList<'static>
 fn score_examples<'v, I>(
     examples: I,
     current: &'v Example,
-    model: &'v FeatureList<'static>,
+    model: &'v Model,
     config: &'v PartConfig,
 ) -> impl Iterator<Item = (Example, f64)> + 'v
 where
     I: Iterator<Item = Example> + 'v,
 {
-    use crate::learn::logistic::predict_iter;
+    use crate::learn::{factorization, logistic};
     examples.map(move |example| {
-        let features = example.features(&current, config);
-        let iter = features.combine(&model).map(|(_, a, b)| (a, b));
-        let score = predict_iter::<f64, _>(iter);
+        let features = model.scale(example.features(&current, config));
+        let iter = features.combine(&model.weights).map(|(_, a, b)| (a, b));
+        let score = if model.factors.is_empty() {
+            logistic::predict_iter::<f64, _>(iter)
+        } else {
+            let factors = features.iter().flat_map(|(k, v)| {
+                model
+                    .factors
+                    .get(k.as_ref())
+                    .map(|factors| (*v, &factors[..]))
+            });
+            factorization::predict_iter(iter, factors)
+        };
         (example, score)
     })
 }
@@ -127,38 +225,40 @@ where
 fn build_activity<T: Store>(
     storage: &T,
     request: &Request,
+    config: &PartConfig,
     current: Example,
     visible: &[(Example, f64)],
+    impressions: Vec<Impression>,
 ) -> Result<Uuid, Error> {
     let activity_id = Uuid::new_v4();
 
     let visible = visible.iter().map(|(e, _)| e.clone()).collect::<Vec<_>>();
 
+    let created = std::time::UNIX_EPOCH
+        .elapsed()
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+
     let activity = Activity {
         id: activity_id,
         part: request.part.clone(),
         current,
         visible,
         chosen: None,
+        event: None,
+        created,
+        impressions,
+        learned: false,
     };
 
     storage.model_activity_save(&request.part, &activity)?;
-    Ok(activity_id)
-}
-
-fn resort_examples(examples: &mut Vec<(Example, f64)>, max: usize, config: &PartConfig) {
-    if max >= examples.len() {
-        return;
-    }
-
-    let mut rng = rand::thread_rng();
-
-    if !rng.gen_bool(config.upgrade_chance) {
-        return;
+    if config.exploration.uses_stats() {
+        let shown = activity
+            .visible
+            .iter()
+            .map(|e| e.item.id)
+            .collect::<Vec<_>>();
+        storage.model_stats_record(&request.part, &shown, &[])?;
     }
-
-    let from = rng.gen_range(max, examples.len());
-    let to = rng.gen_range(0, max);
-
-    examples.swap(to, from);
+    Ok(activity_id)
 }
@@ -0,0 +1,178 @@
+use crate::learn::ftrl::Ftrl;
+use crate::recommend::conf::{Learner, OnlineConfig};
+use crate::recommend::train::generate_features;
+use crate::recommend::{pluck_model, Core};
+use crate::storage::{Model, Store};
+use failure::Error;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// The models of the parts that learn online, as they are being updated.
+/// These are served in place of the stored models, and are written back to
+/// storage every so often.
+#[derive(Debug, Default)]
+pub struct OnlineModels {
+    parts: Mutex<OnlineParts>,
+    /// Held while the model of a part is written to storage, so that a
+    /// model that was learned online is never written over a version that
+    /// was just activated.
+    writes: Mutex<()>,
+}
+
+#[derive(Debug, Default)]
+struct OnlineParts {
+    states: HashMap<String, OnlineState>,
+    /// The number of times each part has been reset.  A model that was
+    /// learned from before the part was reset isn't kept or written.
+    generations: HashMap<String, u64>,
+}
+
+impl OnlineParts {
+    fn generation(&self, part: &str) -> u64 {
+        self.generations.get(part).cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+struct OnlineState {
+    learner: Ftrl<f64>,
+    /// The model as it is now.  It's shared with the recommendations that
+    /// are using it, and copied when it's updated while they still are.
+    model: Arc<Model>,
+    /// The number of activities learned from since the model was last
+    /// written to storage.
+    pending: usize,
+    persisted: Instant,
+}
+
+impl OnlineState {
+    fn new(config: &OnlineConfig, model: Model) -> OnlineState {
+        let weights = model.weights.iter().map(|(k, v)| (k.as_ref(), *v));
+        OnlineState {
+            learner: config.parameters.build_with_weights(weights),
+            model: Arc::new(model),
+            pending: 0,
+            persisted: Instant::now(),
+        }
+    }
+}
+
+impl OnlineModels {
+    /// The current model of the part, if it has learned online since it was
+    /// last reset.
+    pub fn find(&self, part: &str) -> Option<Arc<Model>> {
+        self.parts()
+            .states
+            .get(part)
+            .map(|state| state.model.clone())
+    }
+
+    /// Forgets the part's online state, so that it starts again from the
+    /// stored model the next time it learns.  This happens whenever a new
+    /// version is activated for the part, after its model is stored, and
+    /// while the writes are held.
+    pub fn reset(&self, part: &str) {
+        let mut parts = self.parts();
+        parts.states.remove(part);
+        *parts.generations.entry(part.to_owned()).or_default() += 1;
+    }
+
+    /// Keeps any model learned online from being written to storage, until
+    /// the guard is dropped.
+    pub(crate) fn writes(&self) -> MutexGuard<'_, ()> {
+        self.writes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn parts(&self) -> MutexGuard<'_, OnlineParts> {
+        self.parts.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Store + 'static> Core<T> {
+    /// Updates the model of the part with an activity that just had an item
+    /// chosen, if the part learns online.  Only the linear weights are
+    /// updated, so parts that are trained as factorization machines don't
+    /// learn online; their factors would no longer fit the weights.  The
+    /// model is written to storage once enough activities have been learned
+    /// from, or enough time has passed.  Each activity is only learned from
+    /// once, even if more of its items are chosen.
+    pub fn learn_online(&self, part: &str, activity: Uuid) -> Result<(), Error> {
+        let config = self.config_for(part);
+        let online = match (config.online.as_ref(), config.learner) {
+            (_, Learner::Factorization(_)) | (None, _) => return Ok(()),
+            (Some(online), _) => online,
+        };
+        let activity = match self.storage.model_activity_learn(part, activity)? {
+            Some(activity) => activity,
+            None => return Ok(()),
+        };
+        // The stored model is read before the lock is taken, so that other
+        // parts aren't kept waiting on storage.
+        let (learning, generation) = {
+            let parts = self.online.parts();
+            (parts.states.contains_key(part), parts.generation(part))
+        };
+        let stored = if learning {
+            None
+        } else {
+            Some(pluck_model(self.storage.as_ref(), part)?)
+        };
+
+        let persist = {
+            let mut parts = self.online.parts();
+            // If a version was activated since the generation was read, the
+            // model might be out of date.  The activity is still trained on
+            // with the rest, so it's only skipped here.
+            if parts.generation(part) != generation {
+                return Ok(());
+            }
+            let state = match stored {
+                Some(model) => parts
+                    .states
+                    .entry(part.to_owned())
+                    .or_insert_with(|| OnlineState::new(online, model)),
+                None => parts.states.get_mut(part).unwrap(),
+            };
+            let model = Arc::make_mut(&mut state.model);
+
+            // The model is offset for the negative examples the batch model
+            // wasn't trained on, so rather than leaving them out, they're
+            // given the weight they'd have on average.
+            let rate = config.negative_rate();
+            for (features, target, weight) in generate_features(&activity, config) {
+                let weight = if target > 0.0 { weight } else { weight * rate };
+                let features = model.scale(features);
+                let example = features
+                    .iter()
+                    .map(|(k, v)| (k.as_ref(), *v))
+                    .collect::<Vec<_>>();
+                state.learner.update(&example, target, weight);
+                for (key, _) in example {
+                    let weight = state.learner.weight(key);
+                    model.weights.insert(key.to_owned(), weight);
+                }
+            }
+
+            state.pending += 1;
+            let interval = Duration::from_secs(online.persist_interval);
+            if state.pending >= online.persist_every || state.persisted.elapsed() >= interval {
+                state.pending = 0;
+                state.persisted = Instant::now();
+                Some(state.model.clone())
+            } else {
+                None
+            }
+        };
+
+        if let Some(model) = persist {
+            let _writes = self.online.writes();
+            if self.online.parts().generation(part) == generation {
+                self.storage.set_model(part, Model::clone(&model))?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+use crate::recommend::{pluck_model, score_examples, Core, Criterion, PartConfig};
+use crate::storage::{Activity, Example, Model, Store};
+use failure::Error;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// The criteria every candidate is measured with when activities are
+/// replayed.
+const CRITERIA: &[Criterion] = &[
+    Criterion::Auc,
+    Criterion::PairwiseAccuracy,
+    Criterion::Ndcg { k: 10 },
+    Criterion::Mrr,
+    Criterion::Precision { k: 10 },
+    Criterion::Recall { k: 10 },
+    Criterion::LogLoss,
+];
+
+/// A model and configuration to replay the logged activities of a part
+/// through.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub name: String,
+    pub model: Model,
+    pub config: PartConfig,
+}
+
+/// The result of replaying the logged activities of a part through each of
+/// the candidates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReplayReport {
+    pub part: String,
+    pub activities: usize,
+    /// The number of activities that had an item chosen.  Only these are
+    /// used for the ranking metrics.
+    pub chosen: usize,
+    /// How often the first item shown was chosen, as it was logged.
+    pub logged_ctr: f64,
+    pub candidates: Vec<CandidateReport>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CandidateReport {
+    pub name: String,
+    /// The performance of the candidate by each of the criteria, over the
+    /// activities that had an item chosen.
+    pub metrics: Vec<(Criterion, f64)>,
+    /// The inverse propensity scored estimate of how often the first item
+    /// the candidate would show is chosen.
+    pub ips_ctr: f64,
+    /// The self-normalized version of the estimate, which has less variance
+    /// when few activities match.
+    pub snips_ctr: f64,
+    /// The number of activities where the candidate would have shown the
+    /// same first item as was logged.
+    pub matched: usize,
+}
+
+impl<T: Store + 'static> Core<T> {
+    /// The part's current model and configuration, as a candidate to
+    /// compare others against.
+    pub fn current_candidate(&self, part: &str) -> Result<Candidate, Error> {
+        Ok(Candidate {
+            name: "current".to_owned(),
+            model: pluck_model(self.storage.as_ref(), part)?,
+            config: self.config_for(part).clone(),
+        })
+    }
+
+    /// Replays the part's pending activities through each of the candidates.
+    /// These are only the activities that haven't been trained on yet; once
+    /// they are, they're deleted, so there's no longer history to replay.
+    /// The activities are left in storage.
+    pub fn replay(&self, part: &str, candidates: &[Candidate]) -> Result<ReplayReport, Error> {
+        let activities = self.storage.model_activity_list_part(part)?;
+        Ok(replay(part, &activities, candidates))
+    }
+}
+
+/// Replays the activities through each of the candidates.  Each candidate
+/// re-ranks the items that were shown in each activity, which is compared
+/// to the items that were chosen.
+pub fn replay(part: &str, activities: &[Activity], candidates: &[Candidate]) -> ReplayReport {
+    let activities = activities
+        .iter()
+        .filter(|activity| !activity.visible.is_empty())
+        .collect::<Vec<_>>();
+    let chosen = activities
+        .iter()
+        .filter(|activity| activity.chosen.is_some())
+        .count();
+    let logged = activities.iter().filter(|a| reward(a, 0) > 0.0).count();
+    let logged_ctr = logged as f64 / activities.len().max(1) as f64;
+
+    ReplayReport {
+        part: part.to_owned(),
+        activities: activities.len(),
+        chosen,
+        logged_ctr,
+        candidates: candidates
+            .iter()
+            .map(|candidate| replay_candidate(candidate, &activities))
+            .collect(),
+    }
+}
+
+fn replay_candidate(candidate: &Candidate, activities: &[&Activity]) -> CandidateReport {
+    let (model, config) = (&candidate.model, &candidate.config);
+    let mut groups = vec![];
+    let (mut ips, mut normalizer, mut matched) = (0.0, 0.0, 0);
+
+    for activity in activities {
+        let examples = activity.visible.iter().cloned();
+        let scored = score_examples(examples, &activity.current, model, config)
+            .map(|(example, score)| (example, model.correct(score)))
+            .collect::<Vec<_>>();
+
+        // The position that the candidate would have shown first.  Ties go
+        // to the earlier position.
+        let first = scored
+            .iter()
+            .enumerate()
+            .min_by(|(_, (_, a)), (_, (_, b))| b.partial_cmp(a).unwrap_or(Ordering::Equal))
+            .map(|(position, _)| position);
+        if first == Some(0) {
+            let propensity = logged_propensity(activity, 0);
+            ips += reward(activity, 0) / propensity;
+            normalizer += 1.0 / propensity;
+            matched += 1;
+        }
+
+        if let Some(chosen) = activity.chosen.as_ref() {
+            let chosen = chosen.iter().collect::<HashSet<_>>();
+            let group = scored
+                .iter()
+                .map(|(example, score)| (*score, label(&chosen, example)))
+                .collect();
+            groups.push(group);
+        }
+    }
+
+    let metrics = CRITERIA
+        .iter()
+        .map(|criterion| (*criterion, criterion.evaluate(&groups)))
+        .collect();
+
+    CandidateReport {
+        name: candidate.name.clone(),
+        metrics,
+        ips_ctr: ips / activities.len().max(1) as f64,
+        snips_ctr: if normalizer > 0.0 {
+            ips / normalizer
+        } else {
+            0.0
+        },
+        matched,
+    }
+}
+
+/// The probability that the item shown at the position of the activity was
+/// put there by the policy that logged it.  For activities that didn't record
+/// it, the logging policy is treated as deterministic.
+fn logged_propensity(activity: &Activity, position: usize) -> f64 {
+    activity
+        .impression(position)
+        .map(|impression| impression.propensity)
+        .filter(|propensity| *propensity > 0.0)
+        .unwrap_or(1.0)
+}
+
+/// Whether the item shown at the position of the activity was chosen.
+fn reward(activity: &Activity, position: usize) -> f64 {
+    let item = activity.visible.get(position).map(|e| e.item.id);
+    match (activity.chosen.as_ref(), item) {
+        (Some(chosen), Some(item)) if chosen.contains(&item) => 1.0,
+        _ => 0.0,
+    }
+}
+
+fn label(chosen: &HashSet<&Uuid>, example: &Example) -> f64 {
+    if chosen.contains(&example.item.id) {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{BasicExample, FeatureList, Item};
+
+    #[test]
+    fn it_replays_activities() {
+        let example = |rank: f64| {
+            let id = Uuid::new_v4();
+            let mut basic = BasicExample::new(id);
+            basic.near = Some((1.0, rank).into());
+            Example::new(basic, Item::with_id(id))
+        };
+        let (current, a, b) = (example(0.0), example(1.0), example(5.0));
+        let activity = |visible: Vec<&Example>, chosen: Option<&Example>| Activity {
+            id: Uuid::new_v4(),
+            part: "default".into(),
+            current: current.clone(),
+            visible: visible.into_iter().cloned().collect(),
+            chosen: chosen.map(|e| vec![e.item.id]),
+            event: None,
+            created: 0,
+            impressions: vec![],
+            learned: false,
+        };
+        let activities = vec![
+            activity(vec![&a, &b], Some(&a)),
+            activity(vec![&a, &b], None),
+            activity(vec![&b, &a], Some(&b)),
+        ];
+        let candidate = |name: &str, weight: f64| Candidate {
+            name: name.into(),
+            model: Model::linear(
+                vec![("list:near:rank", weight)]
+                    .into_iter()
+                    .collect::<FeatureList>(),
+            ),
+            config: PartConfig::default(),
+        };
+
+        let report = replay(
+            "default",
+            &activities,
+            &[candidate("low", -1.0), candidate("high", 1.0)],
+        );
+        assert_eq!(report.activities, 3);
+        assert_eq!(report.chosen, 2);
+        assert!((report.logged_ctr - 2.0 / 3.0).abs() < 1e-9);
+
+        let (low, high) = (&report.candidates[0], &report.candidates[1]);
+        assert_eq!(low.matched, 2);
+        assert!((low.ips_ctr - 1.0 / 3.0).abs() < 1e-9);
+        assert!((low.snips_ctr - 0.5).abs() < 1e-9);
+        assert_eq!(high.matched, 1);
+        assert!((high.snips_ctr - 1.0).abs() < 1e-9);
+
+        let mrr = |c: &CandidateReport| {
+            c.metrics
+                .iter()
+                .find(|(criterion, _)| *criterion == Criterion::Mrr)
+                .map(|(_, v)| *v)
+        };
+        assert_eq!(mrr(low), Some(0.75));
+        assert_eq!(mrr(high), Some(0.75));
+    }
+}
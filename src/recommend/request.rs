@@ -1,7 +1,7 @@
-use crate::recommend::Core;
+use crate::recommend::{Core, Filter};
 use crate::storage::{BasicExample, Example, Item, Store, TimeScope};
 use failure::Error;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::{Deref, DerefMut};
 use uuid::Uuid;
 
@@ -15,8 +15,18 @@ pub struct Request {
     pub current: Option<Uuid>,
     #[serde(alias = "w")]
     pub whitelist: Option<Vec<Uuid>>,
+    /// Items that are never recommended.
+    #[serde(default, alias = "x")]
+    pub exclude: Vec<Uuid>,
+    /// If given, only items that match this are recommended.  This replaces
+    /// the part's filter.
+    #[serde(default, alias = "f")]
+    pub filter: Option<Filter>,
     #[serde(alias = "c")]
     pub count: usize,
+    /// Whether to explain why each item was recommended.
+    #[serde(default, alias = "e")]
+    pub explain: bool,
 }
 
 impl Request {
@@ -40,34 +50,58 @@ impl Request {
         &'t self,
         core: &Core<T>,
     ) -> Result<impl Iterator<Item = Example> + 't, Error> {
-        let candidates = self.candidates(core)?;
+        let config = core.config_for(&self.part);
+        let max = config.max_candidate_count;
+        let filter = self.filter.as_ref().or(config.filter.as_ref()).cloned();
+        // Candidates that are filtered out are replaced by ones from further
+        // down the lists, so more of them are looked at before filtering.
+        let candidates = match filter {
+            Some(_) => {
+                let limit = max.saturating_mul(config.filter_candidate_factor);
+                self.candidates_up_to(core, limit)?
+            }
+            None => self.candidates_up_to(core, max)?,
+        };
         let buf = BufIter::new(candidates.into_iter(), 32);
         let storage = core.storage.clone();
-        let iter = buf.flat_map(move |group: Vec<BasicExample>| {
-            let result = storage.find_items(&self.part, group.iter().map(|e| e.id));
-            result
-                .ok()
-                .into_iter()
-                .flatten()
-                .zip(group.into_iter())
-                .flat_map(|(i, ex)| i.map(|item| ex.complete(item)))
-        });
+        let iter = buf
+            .flat_map(move |group: Vec<BasicExample>| {
+                let result = storage.find_items(&self.part, group.iter().map(|e| e.id));
+                result
+                    .ok()
+                    .into_iter()
+                    .flatten()
+                    .zip(group.into_iter())
+                    .flat_map(|(i, ex)| i.map(|item| ex.complete(item)))
+            })
+            .filter(move |ex| filter.iter().all(|f| f.matches(&ex.item)))
+            .take(max);
 
         Ok(iter)
     }
 
     pub fn candidates<T: Store>(&self, core: &Core<T>) -> Result<Vec<BasicExample>, Error> {
         let max = core.config_for(&self.part).max_candidate_count;
+        self.candidates_up_to(core, max)
+    }
+
+    fn candidates_up_to<T: Store>(
+        &self,
+        core: &Core<T>,
+        max: usize,
+    ) -> Result<Vec<BasicExample>, Error> {
+        let excluded = self.excluded(core)?;
         if let Some(list) = self.whitelist.as_ref() {
             return Ok(list
                 .iter()
                 .cloned()
+                .filter(|id| !excluded.contains(id))
                 .map(BasicExample::new)
                 .take(max)
                 .collect());
         }
 
-        let mut candidate_list = CandidateList::new(max * 2);
+        let mut candidate_list = CandidateList::new(max.saturating_mul(2));
 
         let storage = core.storage.clone();
         let list = if let Some(id) = self.current {
@@ -106,6 +140,7 @@ impl Request {
 
         let mut list = candidate_list
             .values()
+            .filter(|ex| !excluded.contains(&ex.id))
             .cloned()
             .take(max)
             .collect::<Vec<BasicExample>>();
@@ -113,6 +148,23 @@ impl Request {
 
         Ok(list)
     }
+
+    /// The items that can't be recommended: the current item, the ones the
+    /// request excludes, and, if the part is configured to, the ones the user
+    /// has already viewed.
+    fn excluded<T: Store>(&self, core: &Core<T>) -> Result<HashSet<Uuid>, Error> {
+        let mut excluded = self
+            .current
+            .iter()
+            .chain(self.exclude.iter())
+            .cloned()
+            .collect::<HashSet<_>>();
+        if core.config_for(&self.part).exclude_history {
+            let user = core.storage.find_user(&self.part, &self.user)?;
+            excluded.extend(user.history);
+        }
+        Ok(excluded)
+    }
 }
 
 #[derive(Debug)]
@@ -1,63 +1,646 @@
+use crate::learn::calibration::Calibration;
+use crate::learn::factorization::FactorizationMachine;
 use crate::learn::logistic::LogisticRegression;
-use crate::learn::metrics::roc_auc_score;
-use crate::learn::{Algorithm, Vector};
-use crate::recommend::{Core, PartConfig};
-use crate::storage::{Activity, FeatureList, Store};
+use crate::learn::{Algorithm, Convergence, Parameters, SparseVector, Vector};
+use crate::recommend::{
+    pluck_model, Core, Criterion, Learner, Objective, PartConfig, ValidationConfig, DEFAULT_PART,
+};
+use crate::storage::{Activity, FeatureList, Model, ModelVersion, Scaling, Store};
 use failure::Error;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// The minimum number of examples a part needs to have accumulated before
+/// it gets its own model trained.  Parts with fewer examples than this are
+/// pooled together and used to train the default model instead.
+const MINIMUM_EXAMPLES: usize = 64;
+
+/// The examples generated from a single activity, each labelled with
+/// whether or not it was chosen, and weighted by how much it should count
+/// for in training.
+type Group<F> = Vec<(F, f64, f64)>;
+
+/// The result of a training run on a single set of examples.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TrainReport {
+    pub examples: usize,
+    pub training: usize,
+    pub holdout: usize,
+    /// The number of chosen examples in the holdout.
+    pub positives: usize,
+    /// The number of folds the performance was cross-validated over, if it
+    /// was.  The holdout is then every example, since each one is held out
+    /// of one of the folds.
+    pub folds: Option<usize>,
+    /// The number of sets of parameters that were tried.  The reported
+    /// performance is that of the best one.
+    pub candidates: usize,
+    /// What the performance of the models was measured with.
+    pub criterion: Criterion,
+    pub old_performance: f64,
+    pub new_performance: f64,
+    /// Whether the newly trained model performed better than the old one.
+    pub improved: bool,
+    /// Why the newly trained model wasn't allowed to be promoted, if it
+    /// failed any of the promotion guards.
+    pub rejection: Option<Rejection>,
+    /// Whether the newly trained model was made the active one.  This can
+    /// only happen if it improved, passed the promotion guards, and the part
+    /// doesn't have a pinned version.
+    pub promoted: bool,
+    /// The version the newly trained model was saved as.
+    pub version: Uuid,
+    /// How training the newly trained model went.
+    pub convergence: Convergence<f64>,
+}
+
+/// A promotion guard that a newly trained model failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "reason")]
+pub enum Rejection {
+    /// A weight or factor of the feature isn't finite.
+    NonFinite {
+        feature: String,
+    },
+    SmallHoldout {
+        holdout: usize,
+        minimum: usize,
+    },
+    FewPositives {
+        positives: usize,
+        minimum: usize,
+    },
+    LowPerformance {
+        performance: f64,
+        threshold: f64,
+    },
+    LargeWeight {
+        feature: String,
+        weight: f64,
+        maximum: f64,
+    },
+    LargeChange {
+        feature: String,
+        from: f64,
+        to: f64,
+        maximum: f64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "outcome")]
+pub enum TrainOutcome {
+    /// There weren't enough examples to train on; nothing was changed.
+    Skipped {
+        examples: usize,
+    },
+    Trained(Box<TrainReport>),
+}
 
 impl<T: Store + 'static> Core<T> {
+    /// Plucks every activity, and trains on them.  The activities of the
+    /// parts that weren't trained are put back, so that they can be trained
+    /// on once there are enough of them; the rest are deleted.
     pub fn load_train(&self) -> Result<(), Error> {
         let activities = self.storage.model_activity_pluck()?;
-        dbg!(&activities);
-        self.train(&activities)?;
-        self.storage
-            .model_activity_delete_all(activities.iter().map(|act| (&act.part[..], act.id)))?;
+        let skipped = self.train(&activities)?;
+        let (skipped, trained) = activities
+            .iter()
+            .map(|act| (&act.part[..], act.id))
+            .partition::<Vec<_>, _>(|(part, _)| skipped.contains(part));
+        self.storage.model_activity_delete_all(trained)?;
+        self.storage.model_activity_restore(skipped)?;
         Ok(())
     }
 
-    pub fn train(&self, activities: &[Activity]) -> Result<(), Error> {
-        let mut list = self.storage.find_default_model()?;
+    /// Like [`load_train`], but only plucks and trains on the activities of
+    /// the given part; the activities of other parts are left for later.  If
+    /// the part isn't trained, its activities are put back, so that they
+    /// can be trained on once there are enough of them.
+    pub fn load_train_part(&self, part: &str) -> Result<TrainOutcome, Error> {
+        let activities = self.storage.model_activity_pluck_part(part)?;
+        let ids = activities.iter().map(|act| (&act.part[..], act.id));
+        let result = self.train_part(part, &activities.iter().collect::<Vec<_>>());
+        match result {
+            Ok(TrainOutcome::Trained(_)) => self.storage.model_activity_delete_all(ids)?,
+            _ => self.storage.model_activity_restore(ids)?,
+        }
+        result
+    }
+
+    /// Trains every part that has activities in the given list.  Each part
+    /// is trained only on its own activities, starting from its own model (or
+    /// the default model, if it doesn't have one yet), and the resulting
+    /// model is promoted for that part only if it performs better.  The
+    /// default model's versions are kept under [`DEFAULT_PART`].  Any part
+    /// that doesn't have enough examples to train on its own is instead used
+    /// to train the default model.  Returns the parts whose activities
+    /// weren't trained on at all, because the default model didn't have
+    /// enough examples to train on either.
+    pub fn train<'a>(&self, activities: &'a [Activity]) -> Result<Vec<&'a str>, Error> {
+        let mut fallback = vec![];
+        let mut fallback_parts = vec![];
+
+        for (part, activities) in group_by_part(activities) {
+            match self.train_part(part, &activities)? {
+                TrainOutcome::Skipped { .. } => {
+                    fallback.extend(activities);
+                    fallback_parts.push(part);
+                }
+                TrainOutcome::Trained(report) => {
+                    info!("trained part {}: {:?}", part, report);
+                }
+            }
+        }
 
-        let features = complete_generate_features(&self, activities, &mut list);
+        if fallback.is_empty() {
+            return Ok(vec![]);
+        }
 
-        if features.len() < 64 {
-            return Ok(());
+        let model = self.storage.find_default_model()?;
+        let outcome = self.train_list(DEFAULT_PART, model, &fallback)?;
+        info!("trained default model: {:?}", outcome);
+        match outcome {
+            TrainOutcome::Skipped { .. } => Ok(fallback_parts),
+            TrainOutcome::Trained(_) => Ok(vec![]),
         }
+    }
 
-        let keys = compute_keys(&list);
-        let (model, features) = convert_model_examples(&keys, &list, &features);
+    /// Trains a single part on the given activities, all of which should
+    /// belong to that part.  The result is saved as a new version, which is
+    /// promoted if it performs better.
+    pub fn train_part(&self, part: &str, activities: &[&Activity]) -> Result<TrainOutcome, Error> {
+        let model = pluck_model(self.storage.as_ref(), part)?;
+        self.train_list(part, model, activities)
+    }
 
-        let mut lr = self.parameters.build_with_weights(model);
-        let (training, holdout) = normal_split(&features);
-        let old_performance = check_performance(&lr, holdout);
-        lr.train(&training);
-        let new_performance = check_performance(&lr, holdout);
+    fn train_list(
+        &self,
+        part: &str,
+        mut model: Model,
+        activities: &[&Activity],
+    ) -> Result<TrainOutcome, Error> {
+        let groups = complete_generate_features(self, activities, &mut model.weights);
+        let examples = groups.iter().map(Vec::len).sum::<usize>();
 
-        if new_performance > old_performance {
-            let model = lr.weights();
-            let result = keys
+        if examples < MINIMUM_EXAMPLES {
+            return Ok(TrainOutcome::Skipped { examples });
+        }
+
+        let config = self.config_for(part);
+        let coverage = compute_coverage(&groups);
+        let scaling = match config.standardization {
+            Some(standardization) => {
+                standardization.fit(groups.iter().flatten().map(|(f, _, _)| f))
+            }
+            None => HashMap::new(),
+        };
+        // The current model is measured on the features scaled the way it was
+        // trained on them, and the new model on the features scaled the new
+        // way.
+        let scale = |scaling: &HashMap<String, Scaling>| {
+            groups
                 .iter()
-                .zip(model.iter())
-                .map(|(k, v)| (*k, *v))
-                .collect::<FeatureList<'_>>();
-            self.storage.set_default_model(result)?;
+                .map(|group| {
+                    group
+                        .iter()
+                        .map(|(f, v, w)| (Scaling::apply_all(scaling, f.clone()), *v, *w))
+                        .collect()
+                })
+                .collect::<Vec<Group<_>>>()
+        };
+        let current = scale(&model.scaling);
+        let groups = scale(&scaling);
+
+        let candidates = self.learner_candidates(config);
+        let (mut trained, parameters, mut report) = train_model(
+            &candidates,
+            config,
+            &self.validation,
+            &model,
+            (&current, &groups),
+        );
+        trained.offset = config.negative_offset();
+        trained.coverage = coverage;
+        trained.scaling = scaling;
+        report.rejection = self.validation.guards.check(&report, &model, &trained);
+        if let Some(rejection) = report.rejection.as_ref() {
+            warn!("rejected model for {}: {:?}", part, rejection);
         }
+        let model = trained;
+        info!(
+            "trained {} in {} iterations ({:?}), loss {:?}",
+            part,
+            report.convergence.iterations,
+            report.convergence.stop,
+            report.convergence.losses.last()
+        );
+        let version = ModelVersion::new(
+            part,
+            report.examples,
+            report.new_performance,
+            parameters,
+            model,
+            report.convergence.clone(),
+        );
+        report.version = version.id;
+        let promotable = report.improved && report.rejection.is_none();
+        report.promoted = self.submit_version(&version, promotable)?;
 
-        Ok(())
+        Ok(TrainOutcome::Trained(Box::new(report)))
+    }
+
+    /// The parameters that the models of a part with the given configuration
+    /// are trained with.  If a search is configured for logistic regression,
+    /// this is every set of parameters it should try; otherwise (or if the
+    /// search has nothing to try), it's just the configured parameters.
+    fn learner_candidates(&self, config: &PartConfig) -> Vec<Parameters<f64>> {
+        match config.learner {
+            Learner::Logistic => {
+                let candidates = self.search.candidates(self.parameters);
+                if candidates.is_empty() {
+                    return vec![Parameters::Logistic(self.parameters)];
+                }
+                candidates.into_iter().map(Parameters::Logistic).collect()
+            }
+            Learner::Factorization(parameters) => vec![Parameters::Factorization(parameters)],
+        }
     }
 }
 
-fn normal_split<T>(complete: &[T]) -> (&[T], &[T]) {
-    let split = complete.len() * 2 / 3;
-    (&complete[0..split], &complete[split..])
+/// Trains a model with the best of the candidate parameters, and returns it
+/// along with the parameters it was trained with.  The parameters are picked
+/// on examples that are set aside from the training examples, so that the
+/// holdout the model is compared to the current one on plays no part in the
+/// choice.  The groups are given twice: scaled for the current model, which it's
+/// compared against, and scaled for the new model.
+fn train_model(
+    candidates: &[Parameters<f64>],
+    config: &PartConfig,
+    validation: &ValidationConfig,
+    model: &Model,
+    (current, groups): (
+        &[Group<FeatureList<'static>>],
+        &[Group<FeatureList<'static>>],
+    ),
+) -> (Model, Parameters<f64>, TrainReport) {
+    let keys = compute_keys(&model.weights);
+    let mut current = convert_model_examples(&keys, current);
+    let mut groups = convert_model_examples(&keys, groups);
+    // The activities are plucked in the order they happened, so they're
+    // shuffled to keep the holdout from being only the most recent ones.
+    // Both are shuffled the same way, so that they stay in step.
+    current.shuffle(&mut StdRng::seed_from_u64(validation.seed));
+    groups.shuffle(&mut StdRng::seed_from_u64(validation.seed));
+    let criterion = config.promotion_criterion();
+    let folds = validation
+        .folds
+        .map(|f| f.min(groups.len()))
+        .filter(|f| *f > 1);
+
+    let trainer = Trainer {
+        config,
+        validation,
+        criterion,
+        folds,
+        keys: &keys,
+        model,
+        current: &current,
+        groups: &groups,
+    };
+
+    let best = trainer.trial(candidates);
+
+    let report = TrainReport {
+        examples: groups.iter().map(Vec::len).sum(),
+        training: best.training,
+        holdout: best.holdout,
+        positives: best.positives,
+        folds,
+        candidates: candidates.len(),
+        criterion,
+        old_performance: best.old_performance,
+        new_performance: best.new_performance,
+        improved: criterion.improved(best.old_performance, best.new_performance),
+        rejection: None,
+        promoted: false,
+        version: Uuid::nil(),
+        convergence: best.convergence,
+    };
+
+    (best.model, best.parameters, report)
+}
+
+/// Everything needed to train and validate a model, other than the
+/// parameters to train it with.
+struct Trainer<'t> {
+    config: &'t PartConfig,
+    validation: &'t ValidationConfig,
+    criterion: Criterion,
+    folds: Option<usize>,
+    keys: &'t [&'t str],
+    model: &'t Model,
+    /// The groups, scaled the way the current model was trained on them.
+    current: &'t [Group<SparseVector<f64>>],
+    groups: &'t [Group<SparseVector<f64>>],
 }
 
-fn check_performance(lr: &LogisticRegression<f64>, examples: &[(Vector<f64>, f64)]) -> f64 {
-    let hat = lr
-        .predict(examples.iter().map(|(a, _)| a))
-        .collect::<Vec<_>>();
-    let tru = examples.iter().map(|(_, b)| *b).collect::<Vec<_>>();
-    roc_auc_score(&tru, &hat)
+/// A model trained with a single set of parameters.
+struct Trial {
+    parameters: Parameters<f64>,
+    model: Model,
+    training: usize,
+    holdout: usize,
+    positives: usize,
+    old_performance: f64,
+    new_performance: f64,
+    convergence: Convergence<f64>,
+}
+
+impl<'t> Trainer<'t> {
+    /// Trains and validates a model with the best of the candidates.  With
+    /// folds, the best is searched for within each fold's training examples,
+    /// so that the performance is that of the whole search.
+    fn trial(&self, candidates: &[Parameters<f64>]) -> Trial {
+        let (keys, criterion) = (self.keys, self.criterion);
+        let offset = self.config.negative_offset();
+
+        match self.folds {
+            Some(folds) => {
+                let (mut old_performance, mut new_performance) = (0.0, 0.0);
+                let mut scored = vec![];
+                for fold in 0..folds {
+                    let (training, holdout) = fold_split(self.groups, folds, fold);
+                    let (_, current) = fold_split(self.current, folds, fold);
+                    let parameters = self.search(candidates, &training);
+                    let mut learner = build_learner(parameters, keys, self.model);
+                    old_performance +=
+                        check_performance(learner.as_ref(), criterion, &current, self.model.offset);
+                    self.fit(learner.as_mut(), parameters, &training);
+                    let fold_scored = score_groups(learner.as_ref(), &holdout, offset);
+                    new_performance += criterion.evaluate(&fold_scored);
+                    scored.extend(fold_scored.into_iter().flatten());
+                }
+
+                let parameters = self.search(candidates, self.groups);
+                let mut learner = build_learner(parameters, keys, self.model);
+                let (training, convergence) = self.fit(learner.as_mut(), parameters, self.groups);
+                Trial {
+                    parameters,
+                    model: self.calibrate(learner.to_model(keys), &scored),
+                    training,
+                    holdout: self.groups.iter().map(Vec::len).sum(),
+                    positives: count_positives(self.groups),
+                    old_performance: old_performance / folds as f64,
+                    new_performance: new_performance / folds as f64,
+                    convergence,
+                }
+            }
+            None => {
+                let ratio = self.validation.holdout_ratio;
+                let (training, holdout) = holdout_split(self.groups, ratio);
+                let (_, current) = holdout_split(self.current, ratio);
+                let parameters = self.search(candidates, training);
+                let mut learner = build_learner(parameters, keys, self.model);
+                let old_performance =
+                    check_performance(learner.as_ref(), criterion, current, self.model.offset);
+                let (training, convergence) = self.fit(learner.as_mut(), parameters, training);
+                let scored = score_groups(learner.as_ref(), holdout, offset);
+                let new_performance = criterion.evaluate(&scored);
+                let scored = scored.into_iter().flatten().collect::<Vec<_>>();
+                Trial {
+                    parameters,
+                    model: self.calibrate(learner.to_model(keys), &scored),
+                    training,
+                    holdout: holdout.iter().map(Vec::len).sum(),
+                    positives: count_positives(holdout),
+                    old_performance,
+                    new_performance,
+                    convergence,
+                }
+            }
+        }
+    }
+
+    /// Picks the candidate that performs the best when it's trained on some
+    /// of the groups and validated on the rest.  The split is the same as
+    /// the holdout's, within the groups.
+    fn search(
+        &self,
+        candidates: &[Parameters<f64>],
+        groups: &[Group<SparseVector<f64>>],
+    ) -> Parameters<f64> {
+        if let [parameters] = candidates {
+            return *parameters;
+        }
+
+        let (training, validation) = holdout_split(groups, self.validation.holdout_ratio);
+        let mut best: Option<(Parameters<f64>, f64)> = None;
+        for parameters in candidates {
+            let mut learner = build_learner(*parameters, self.keys, self.model);
+            self.fit(learner.as_mut(), *parameters, training);
+            let offset = self.config.negative_offset();
+            let performance =
+                check_performance(learner.as_ref(), self.criterion, validation, offset);
+            debug!("candidate {:?}: {}", parameters, performance);
+            match best {
+                Some((_, b)) if !self.criterion.improved(b, performance) => {}
+                _ => best = Some((*parameters, performance)),
+            }
+        }
+        best.map(|(parameters, _)| parameters)
+            .expect("there's always at least one candidate")
+    }
+
+    /// Fits the part's calibration to the model's predictions on the examples
+    /// it was held out of, given as `(prediction, label)`.
+    fn calibrate(&self, mut model: Model, scored: &[(f64, f64)]) -> Model {
+        if let Some(method) = self.config.calibration {
+            if !scored.is_empty() {
+                model.calibration = method.fit(scored);
+            }
+        }
+        model
+    }
+
+    /// Trains the learner on the groups, returning the number of examples it
+    /// was trained on.  If the parameters stop early, some of the groups are
+    /// set aside to decide when to stop, instead of being trained on.  Only
+    /// the examples that are trained on are downsampled.
+    fn fit(
+        &self,
+        learner: &mut dyn Learned,
+        parameters: Parameters<f64>,
+        groups: &[Group<SparseVector<f64>>],
+    ) -> (usize, Convergence<f64>) {
+        let objective = self.config.objective;
+        let (training, stopping) = match parameters.patience() {
+            Some(_) => holdout_split(groups, self.validation.stopping_ratio),
+            None => (groups, &[][..]),
+        };
+        let training = training_examples(objective, &self.downsample(training));
+        let stopping = training_examples(objective, stopping);
+        let convergence = learner.train_with_validation(&training, &stopping);
+        (training.len(), convergence)
+    }
+
+    /// If the part downsamples negative examples, randomly leaves out the
+    /// examples that weren't chosen at its rate, with a generator seeded from
+    /// the validation seed, so that the same examples are left out each time
+    /// the groups are trained on.
+    fn downsample(&self, groups: &[Group<SparseVector<f64>>]) -> Vec<Group<SparseVector<f64>>> {
+        let rate = self.config.negative_rate();
+        let mut rng = StdRng::seed_from_u64(self.validation.seed);
+        groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .filter(|(_, label, _)| *label > 0.0 || rate >= 1.0 || rng.gen_bool(rate))
+                    .cloned()
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// A learning algorithm that can be turned back into a model to store.
+trait Learned: Algorithm<f64> {
+    fn to_model(&self, keys: &[&str]) -> Model;
+}
+
+impl Learned for LogisticRegression<f64> {
+    fn to_model(&self, keys: &[&str]) -> Model {
+        Model::linear(convert_weights(keys, self.weights()))
+    }
+}
+
+impl Learned for FactorizationMachine<f64> {
+    fn to_model(&self, keys: &[&str]) -> Model {
+        let factors = keys
+            .iter()
+            .zip(self.factors().iter())
+            .map(|(k, v)| (k.to_string(), v.to_vec()))
+            .collect();
+        Model {
+            weights: convert_weights(keys, self.weights()),
+            factors,
+            offset: 0.0,
+            scaling: HashMap::new(),
+            calibration: Calibration::Identity,
+            coverage: HashMap::new(),
+        }
+    }
+}
+
+/// Sets up the learning algorithm the parameters are for, starting from the
+/// given model.
+fn build_learner(parameters: Parameters<f64>, keys: &[&str], model: &Model) -> Box<dyn Learned> {
+    let weights = model.weights.to_vector(keys);
+    match parameters {
+        Parameters::Logistic(parameters) => Box::new(parameters.build_with_weights(weights)),
+        Parameters::Factorization(parameters) => {
+            let factors = keys
+                .iter()
+                .map(|k| model.factors.get(*k).cloned().unwrap_or_default().into())
+                .collect();
+            Box::new(parameters.build_with(weights, factors))
+        }
+    }
+}
+
+fn training_examples(
+    objective: Objective,
+    groups: &[Group<SparseVector<f64>>],
+) -> Vec<(SparseVector<f64>, f64, f64)> {
+    match objective {
+        Objective::Pointwise => groups.iter().flatten().cloned().collect(),
+        Objective::Pairwise => groups.iter().flat_map(generate_pairs).collect(),
+    }
+}
+
+fn group_by_part(activities: &[Activity]) -> HashMap<&str, Vec<&Activity>> {
+    let mut parts = HashMap::<_, Vec<_>>::new();
+    for activity in activities {
+        parts.entry(&activity.part[..]).or_default().push(activity);
+    }
+    parts
+}
+
+/// Splits off the given fraction of the groups to hold out of training.
+fn holdout_split<T>(complete: &[T], ratio: f64) -> (&[T], &[T]) {
+    let holdout = (complete.len() as f64 * ratio.clamp(0.0, 1.0)).round() as usize;
+    complete.split_at(complete.len() - holdout)
+}
+
+/// Splits the groups into the ones to train on and the ones to hold out, for
+/// the given fold of a k-fold cross-validation.
+fn fold_split<T: Clone>(complete: &[T], folds: usize, fold: usize) -> (Vec<T>, Vec<T>) {
+    let (holdout, training) = complete
+        .iter()
+        .enumerate()
+        .partition::<Vec<_>, _>(|(i, _)| i % folds == fold);
+    let strip = |list: Vec<(usize, &T)>| list.into_iter().map(|(_, v)| v.clone()).collect();
+    (strip(training), strip(holdout))
+}
+
+fn count_positives(groups: &[Group<SparseVector<f64>>]) -> usize {
+    groups
+        .iter()
+        .flatten()
+        .filter(|(_, label, _)| *label > 0.0)
+        .count()
+}
+
+fn check_performance(
+    algorithm: &dyn Learned,
+    criterion: Criterion,
+    groups: &[Group<SparseVector<f64>>],
+    offset: f64,
+) -> f64 {
+    criterion.evaluate(&score_groups(algorithm, groups, offset))
+}
+
+/// Scores the examples of each group, pairing each score with its label.
+/// The scores are offset the way the model's predictions will be, to
+/// correct for the negative examples it wasn't trained on.
+fn score_groups(
+    algorithm: &dyn Learned,
+    groups: &[Group<SparseVector<f64>>],
+    offset: f64,
+) -> Vec<Vec<(f64, f64)>> {
+    groups
+        .iter()
+        .map(|group| {
+            let hat = algorithm.predict_iter(Box::new(group.iter().map(|(a, _, _)| a)));
+            hat.map(|p| Model::offset_prediction(p, offset))
+                .zip(group.iter().map(|(_, b, _)| *b))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// The fraction of the examples that have each feature, with a non-zero
+/// value.
+fn compute_coverage(groups: &[Group<FeatureList<'static>>]) -> HashMap<String, f64> {
+    let total = groups.iter().map(Vec::len).sum::<usize>().max(1) as f64;
+    let mut coverage = HashMap::<String, f64>::new();
+    for (features, _, _) in groups.iter().flatten() {
+        for (key, value) in features.iter() {
+            if *value != 0.0 {
+                *coverage.entry(key.to_string()).or_default() += 1.0;
+            }
+        }
+    }
+
+    for value in coverage.values_mut() {
+        *value /= total;
+    }
+    coverage
 }
 
 fn compute_keys<'l>(list: &'l FeatureList<'static>) -> Vec<&'l str> {
@@ -66,70 +649,105 @@ fn compute_keys<'l>(list: &'l FeatureList<'static>) -> Vec<&'l str> {
     keys
 }
 
+fn convert_weights(keys: &[&str], weights: &Vector<f64>) -> FeatureList<'static> {
+    keys.iter()
+        .zip(weights.iter())
+        .map(|(k, v)| (k.to_string(), *v))
+        .collect()
+}
+
 fn convert_model_examples(
     keys: &[&str],
-    list: &FeatureList<'static>,
-    examples: &[(FeatureList<'static>, f64)],
-) -> (Vector<f64>, Vec<(Vector<f64>, f64)>) {
-    let model = list.to_vector(&keys);
-    let features = examples
-        .into_iter()
-        .map(|(f, v)| (f.to_vector(&keys), *v))
-        .collect::<Vec<_>>();
-    (model, features)
+    groups: &[Group<FeatureList<'static>>],
+) -> Vec<Group<SparseVector<f64>>> {
+    let index = keys
+        .iter()
+        .enumerate()
+        .map(|(i, k)| (*k, i))
+        .collect::<HashMap<_, _>>();
+    groups
+        .iter()
+        .map(|group| {
+            group
+                .iter()
+                .map(|(f, v, w)| (f.to_sparse(&index), *v, *w))
+                .collect()
+        })
+        .collect()
 }
 
 fn complete_generate_features<T: Store + 'static>(
     core: &Core<T>,
-    activities: &[Activity],
+    activities: &[&Activity],
     list: &mut FeatureList<'static>,
-) -> Vec<(FeatureList<'static>, f64)> {
-    let features = activities
+) -> Vec<Group<FeatureList<'static>>> {
+    let groups = activities
         .iter()
-        .flat_map(|activity| {
+        .map(|activity| {
             let part = core.config_for(&activity.part);
-            generate_features(activity, part).into_iter()
+            generate_features(activity, part).collect()
         })
-        .collect::<Vec<_>>();
+        .collect::<Vec<Group<_>>>();
 
-    for (f, _) in features.iter() {
+    for (f, _, _) in groups.iter().flatten() {
         for key in f.keys() {
             list.ensure_has(key);
         }
     }
 
-    features
+    groups
 }
 
-fn generate_features<'v>(
+/// Generates the features of each of the visible examples of the activity,
+/// along with whether it was chosen, and its weight.  If the part learns a
+/// position feature, it's added to the examples that recorded where they
+/// were shown.
+pub(crate) fn generate_features<'v>(
     activity: &'v Activity,
     part: &'v PartConfig,
-) -> impl Iterator<Item = (FeatureList<'static>, f64)> + 'v {
-    activity.visible.iter().map(move |example| {
+) -> impl Iterator<Item = (FeatureList<'static>, f64, f64)> + 'v {
+    let now = std::time::UNIX_EPOCH
+        .elapsed()
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+
+    let visible = activity.visible.iter().enumerate();
+    visible.map(move |(index, example)| {
         let positive = activity
             .chosen
             .as_ref()
             .map(|c| c.contains(&example.item.id))
             .unwrap_or(false);
-        let features = example.features(&activity.current, part);
+        let impression = activity.impression(index);
+        let mut features = example.features(&activity.current, part);
+        if let Some(impression) = impression.filter(|_| part.position_feature) {
+            features.insert("position:ln1p", (impression.position as f64).ln_1p());
+        }
         let value = if positive { 1.0 } else { 0.0 };
-        (features, value)
+        let weight = part.weighting.weight(activity, impression, positive, now);
+        (features, value, weight)
     })
-    //    let features = activity
-    //        .visible
-    //        .iter()
-    //        .map(|ex| (ex, ex.features(&activity.current, part)))
-    //        .collect::<Vec<_>>();
-    //    activity
-    //        .chosen
-    //        .iter()
-    //        .flatten()
-    //        .flat_map(|chosen| features.iter().find(|(ex, _)| ex.item.id == *chosen))
-    //        .flat_map(|(ex, feats): &(&Example, FeatureList<'static>)| {
-    //            features
-    //                .iter()
-    //                .filter(move |(e, _)| e.item.id != ex.item.id)
-    //                .map(move |(_, f)| f - feats)
-    //        })
-    //        .collect()
+}
+
+/// Turns the examples of a single activity into pairs for pairwise
+/// training.  Every chosen example is paired with every example that wasn't
+/// chosen, and the pair is represented by the difference of their features.
+/// Each pair is included both ways around (labelled positive when the chosen
+/// example comes first), so that the model sees both classes.  The weight
+/// of a pair is the product of the weights of its examples.
+fn generate_pairs(group: &Group<SparseVector<f64>>) -> Vec<(SparseVector<f64>, f64, f64)> {
+    let (chosen, other) = group
+        .iter()
+        .partition::<Vec<_>, _>(|(_, label, _)| *label > 0.0);
+
+    chosen
+        .iter()
+        .flat_map(|c| other.iter().map(move |o| (c, o)))
+        .flat_map(|((c, _, cw), (o, _, ow))| {
+            let forward = c.sub(o);
+            let backward = forward.neg();
+            let weight = cw * ow;
+            vec![(forward, 1.0, weight), (backward, 0.0, weight)]
+        })
+        .collect()
 }
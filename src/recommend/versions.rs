@@ -0,0 +1,118 @@
+use crate::recommend::Core;
+use crate::storage::{ModelPointer, ModelVersion, Store};
+use failure::Error;
+use uuid::Uuid;
+
+/// The part name that versions of the default model are stored under.
+pub const DEFAULT_PART: &str = "@default";
+
+impl<T: Store + 'static> Core<T> {
+    /// Saves a newly trained version of a model.  If the version performed
+    /// better than the currently active one, and the part doesn't have a
+    /// pinned version, it becomes the active version for the part.  Returns
+    /// whether or not the version was made active.
+    pub fn submit_version(&self, version: &ModelVersion, improved: bool) -> Result<bool, Error> {
+        self.storage.model_version_save(version)?;
+        let mut pointer = self.storage.model_pointer_find(&version.part)?;
+
+        let activated = improved && !pointer.pinned;
+        if activated {
+            pointer.activate(version.id);
+            self.activate(version, &pointer)?;
+        }
+
+        self.retain_versions(&version.part, pointer)?;
+        Ok(activated)
+    }
+
+    /// Makes the given version the active version of the part, and keeps it
+    /// that way until it is unpinned.  Returns `None` if the version
+    /// doesn't exist.
+    pub fn pin_version(&self, part: &str, id: Uuid) -> Result<Option<ModelPointer>, Error> {
+        let version = match self.storage.model_version_find(part, id)? {
+            Some(version) => version,
+            None => return Ok(None),
+        };
+        let mut pointer = self.storage.model_pointer_find(part)?;
+        pointer.activate(id);
+        pointer.pinned = true;
+        self.activate(&version, &pointer)?;
+        Ok(Some(pointer))
+    }
+
+    /// Allows newly trained versions to replace the active version again.
+    pub fn unpin_version(&self, part: &str) -> Result<ModelPointer, Error> {
+        let mut pointer = self.storage.model_pointer_find(part)?;
+        pointer.pinned = false;
+        self.storage.model_pointer_set(part, &pointer)?;
+        Ok(pointer)
+    }
+
+    /// Reactivates the version that was active before the current one.
+    /// Returns `None` if there is no previous version to roll back to.
+    pub fn rollback_version(&self, part: &str) -> Result<Option<ModelPointer>, Error> {
+        let mut pointer = self.storage.model_pointer_find(part)?;
+        let version = match pointer.rollback() {
+            Some(id) => self.storage.model_version_find(part, id)?,
+            None => None,
+        };
+
+        match version {
+            Some(version) => {
+                self.activate(&version, &pointer)?;
+                Ok(Some(pointer))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Lists every version of the part, oldest first.
+    pub fn versions(&self, part: &str) -> Result<Vec<ModelVersion>, Error> {
+        let mut versions = vec![];
+        for id in self.storage.model_version_list(part)? {
+            versions.extend(self.storage.model_version_find(part, id)?);
+        }
+        Ok(versions)
+    }
+
+    /// Deletes the versions of the part that are older than the ones that
+    /// are kept, except for the active one.  The deleted versions are also
+    /// forgotten by the pointer, so they're never rolled back to.
+    fn retain_versions(&self, part: &str, mut pointer: ModelPointer) -> Result<(), Error> {
+        let list = self.storage.model_version_list(part)?;
+        let count = list.len().saturating_sub(self.versions.retain);
+        let stale = list[..count]
+            .iter()
+            .filter(|id| pointer.active != Some(**id))
+            .cloned()
+            .collect::<Vec<_>>();
+        if stale.is_empty() {
+            return Ok(());
+        }
+
+        for id in &stale {
+            self.storage.model_version_delete(part, *id)?;
+        }
+        let length = pointer.history.len();
+        pointer.history.retain(|id| !stale.contains(id));
+        if pointer.history.len() != length {
+            self.storage.model_pointer_set(part, &pointer)?;
+        }
+        Ok(())
+    }
+
+    /// Saves the pointer, and copies the version's model to where the model
+    /// for the part is served from.
+    fn activate(&self, version: &ModelVersion, pointer: &ModelPointer) -> Result<(), Error> {
+        self.storage.model_pointer_set(&version.part, pointer)?;
+        let _writes = self.online.writes();
+        if version.part == DEFAULT_PART {
+            self.storage.set_default_model(version.model.clone())?;
+        } else {
+            self.storage
+                .set_model(&version.part, version.model.clone())?;
+        }
+        self.online.reset(&version.part);
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use crate::recommend::{Candidate, Core, Criterion, PartConfig, ReplayReport};
+use crate::storage::{DefaultStorage, ModelStore};
+use config::Config;
+use failure::{format_err, Error};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const USAGE: &str = "usage: vilkas replay <part> [--version <id>]... [--config <file>]... [--json]
+replays the activities that are waiting to be trained on; the ones that \
+have been trained on aren't kept";
+
+/// Replays the pending activities of a part through its current model and
+/// configuration, and through each of the given candidates, and prints how
+/// they compare.  Each `--version` is a stored version of the part's model,
+/// replayed with the part's current configuration; each `--config` is a file
+/// with a part configuration, replayed with the part's current model.  The
+/// activities are read from the configured storage, which can be a snapshot
+/// of production, and are left where they are.  Only the activities that
+/// haven't been trained on yet are replayed, since training deletes them.
+pub fn run(config: Config, args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let mut args = args;
+    let part = args.next().ok_or_else(|| format_err!("{}", USAGE))?;
+    let storage = Arc::new(DefaultStorage::load(&config));
+    let core = Core::of(&storage, &config)?;
+    let current = core.current_candidate(&part)?;
+    let mut candidates = vec![current.clone()];
+    let mut json = false;
+
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--version" => {
+                let id = args.next().ok_or_else(|| format_err!("{}", USAGE))?;
+                let id = id.parse::<Uuid>()?;
+                let version = storage
+                    .model_version_find(&part, id)?
+                    .ok_or_else(|| format_err!("no version {} of part {}", id, part))?;
+                candidates.push(Candidate {
+                    name: format!("version:{}", id),
+                    model: version.model,
+                    config: current.config.clone(),
+                });
+            }
+            "--config" => {
+                let path = args.next().ok_or_else(|| format_err!("{}", USAGE))?;
+                let mut file = Config::new();
+                file.merge(config::File::with_name(&path))?;
+                let config = file.try_into::<PartConfig>()?;
+                config.validate()?;
+                candidates.push(Candidate {
+                    name: format!("config:{}", path),
+                    model: current.model.clone(),
+                    config,
+                });
+            }
+            "--json" => json = true,
+            _ => return Err(format_err!("{}", USAGE)),
+        }
+    }
+
+    let report = core.replay(&part, &candidates)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report);
+    }
+    Ok(())
+}
+
+fn print_report(report: &ReplayReport) {
+    println!(
+        "replayed {} pending activities of part {} ({} with items chosen); logged ctr {:.4}",
+        report.activities, report.part, report.chosen, report.logged_ctr
+    );
+
+    let mut columns = report
+        .candidates
+        .first()
+        .map(|c| {
+            c.metrics
+                .iter()
+                .map(|(c, _)| criterion_name(*c))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    columns.extend(vec!["ips-ctr".to_owned(), "snips-ctr".to_owned()]);
+    let name_width = report
+        .candidates
+        .iter()
+        .map(|c| c.name.len())
+        .chain(Some(9))
+        .max()
+        .unwrap_or_default();
+
+    print!("{:<width$}", "candidate", width = name_width);
+    for column in columns {
+        print!("  {:>12}", column);
+    }
+    println!("  {:>8}", "matched");
+
+    for candidate in &report.candidates {
+        print!("{:<width$}", candidate.name, width = name_width);
+        let values = candidate.metrics.iter().map(|(_, v)| *v);
+        for value in values.chain(vec![candidate.ips_ctr, candidate.snips_ctr]) {
+            print!("  {:>12.4}", value);
+        }
+        println!("  {:>8}", candidate.matched);
+    }
+}
+
+fn criterion_name(criterion: Criterion) -> String {
+    match criterion {
+        Criterion::Auc => "auc".to_owned(),
+        Criterion::PairwiseAccuracy => "pairwise-acc".to_owned(),
+        Criterion::Ndcg { k } => format!("ndcg@{}", k),
+        Criterion::Mrr => "mrr".to_owned(),
+        Criterion::Precision { k } => format!("precision@{}", k),
+        Criterion::Recall { k } => format!("recall@{}", k),
+        Criterion::LogLoss => "log-loss".to_owned(),
+    }
+}
@@ -19,6 +19,19 @@ pub struct Item {
     pub meta: HashMap<String, HashSet<String>>,
 }
 
+#[cfg(test)]
+impl Item {
+    /// An item of the default part that hasn't been viewed, and has no meta.
+    pub fn with_id(id: Uuid) -> Item {
+        Item {
+            id,
+            part: "default".into(),
+            views: 0,
+            meta: Default::default(),
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ItemList {
     pub items: Vec<(Uuid, f64)>,
@@ -34,8 +34,9 @@ impl Example {
         }
 
         config.extract_all(&mut feat, &self, current);
+        config.cross_all(&mut feat);
 
-        feat
+        config.hash_features(feat)
     }
 }
 
@@ -106,6 +107,26 @@ impl BasicExample {
         self
     }
 
+    /// The names of the lists this example was a candidate from.
+    pub fn sources(&self) -> Vec<String> {
+        let mut sources = vec![];
+        if self.near.is_some() {
+            sources.push("near".to_owned());
+        }
+        if self.recent.is_some() {
+            sources.push("recent".to_owned());
+        }
+        for scope in TimeScope::variants() {
+            if self.top.contains_key(&scope) {
+                sources.push(format!("top:{}", scope));
+            }
+            if self.pop.contains_key(&scope) {
+                sources.push(format!("pop:{}", scope));
+            }
+        }
+        sources
+    }
+
     pub fn complete(self, item: Item) -> Example {
         Example { basic: self, item }
     }
@@ -0,0 +1,88 @@
+use failure::{format_err, Error};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Starts every record that's stored with the version of its format.
+/// Records that were stored before their formats were versioned start with
+/// the length of a map or a byte string instead, which is never this large.
+const MARKER: u64 = 0x7669_6c6b_6173_0000;
+
+/// The length of the marker and the version that start a record.
+const HEADER: usize = 12;
+
+/// A type that's stored with the version of its format, so that records
+/// stored with older versions of the format can still be read after the
+/// type changes.  When the type changes, its `VERSION` is incremented and
+/// `migrate` learns to read the previous version.
+pub trait Versioned: Serialize + DeserializeOwned {
+    /// The version of the format the type is stored in now.
+    const VERSION: u32;
+
+    /// Reads a record that was stored in an older version of the format.
+    /// Version 0 is the format from before it was versioned.
+    fn migrate(version: u32, data: &[u8]) -> Result<Self, Error>;
+}
+
+pub fn encode<T: Versioned>(value: &T) -> Result<Vec<u8>, Error> {
+    Ok(bincode::serialize(&(MARKER, T::VERSION, value))?)
+}
+
+pub fn decode<T: Versioned>(data: &[u8]) -> Result<T, Error> {
+    match bincode::deserialize::<(u64, u32)>(data) {
+        Ok((MARKER, version)) if version == T::VERSION => {
+            Ok(bincode::deserialize(&data[HEADER..])?)
+        }
+        Ok((MARKER, version)) if version < T::VERSION => T::migrate(version, &data[HEADER..]),
+        Ok((MARKER, version)) => Err(format_err!("unknown format version {}", version)),
+        _ => T::migrate(0, data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Activity, BasicExample, Example, FeatureList, Item, Model};
+    use uuid::Uuid;
+
+    #[test]
+    fn it_reads_models_from_before_the_format_was_versioned() {
+        let weights = vec![("list:near:rank", -0.5), ("list:recent:rank", 0.25)]
+            .into_iter()
+            .collect::<FeatureList>();
+        let legacy = bincode::serialize(&weights).unwrap();
+        let model = decode::<Model>(&legacy).unwrap();
+        assert_eq!(model.weights.get("list:near:rank"), Some(&-0.5));
+        assert!(model.factors.is_empty());
+
+        let mut model = model;
+        model.factors.insert("list:near:rank".to_owned(), vec![0.5]);
+        let stored = decode::<Model>(&encode(&model).unwrap()).unwrap();
+        assert_eq!(stored.factors["list:near:rank"], vec![0.5]);
+        assert_eq!(stored.weights.get("list:recent:rank"), Some(&0.25));
+
+        let mut future = encode(&model).unwrap();
+        future[8] += 1;
+        assert!(decode::<Model>(&future).is_err());
+    }
+
+    #[test]
+    fn it_reads_activities_from_before_the_format_was_versioned() {
+        let example = || {
+            let id = Uuid::new_v4();
+            Example::new(BasicExample::new(id), Item::with_id(id))
+        };
+        let (id, current, visible) = (Uuid::new_v4(), example(), vec![example(), example()]);
+        let chosen = Some(vec![visible[1].item.id]);
+        let legacy = bincode::serialize(&(id, "default", &current, &visible, &chosen)).unwrap();
+
+        let activity = decode::<Activity>(&legacy).unwrap();
+        assert_eq!(activity.id, id);
+        assert_eq!(activity.visible.len(), 2);
+        assert_eq!(activity.chosen, chosen);
+        assert!(activity.event.is_none());
+        assert!(activity.impressions.is_empty());
+
+        let stored = decode::<Activity>(&encode(&activity).unwrap()).unwrap();
+        assert_eq!(stored.chosen, chosen);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::learn::Vector;
+use crate::learn::{SparseVector, Vector};
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::iter::FromIterator;
@@ -26,6 +26,32 @@ impl<'k> FeatureList<'k> {
             .collect()
     }
 
+    /// Converts the list to a sparse vector, using the index of each key.
+    /// Any features that aren't in the index are left out.
+    pub fn to_sparse(&self, index: &HashMap<&str, usize>) -> SparseVector<f64> {
+        self.iter()
+            .flat_map(|(key, value)| index.get(key.as_ref()).map(|idx| (*idx, *value)))
+            .collect()
+    }
+
+    /// Hashes the features with keys that start with the prefix into the
+    /// given number of buckets, named `hash:<bucket>`.  Features that hash
+    /// into the same bucket are summed together; the rest of the features
+    /// are kept as they are.  This bounds the number of features a model
+    /// can have, no matter how many distinct keys there are.
+    pub fn hashed(self, buckets: usize, prefix: &str) -> FeatureList<'static> {
+        let mut list = FeatureList::default();
+        for (key, value) in self {
+            let key = if buckets > 0 && key.starts_with(prefix) {
+                Cow::Owned(format!("hash:{}", hash_key(&key) % buckets as u64))
+            } else {
+                Cow::Owned(key.into_owned())
+            };
+            *list.0.entry(key).or_insert(0.0) += value;
+        }
+        list
+    }
+
     pub fn keys(&self) -> impl Iterator<Item = &Cow<'k, str>> {
         self.0.keys()
     }
@@ -60,6 +86,15 @@ impl<'k> FeatureList<'k> {
     }
 }
 
+/// Hashes the key with 64-bit FNV-1a.  This is used instead of the standard
+/// library's hasher, since the buckets need to be the same across builds and
+/// processes, or stored models would stop matching their features.
+fn hash_key(key: &str) -> u64 {
+    key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
 impl<'k> std::ops::Deref for FeatureList<'k> {
     type Target = HashMap<Cow<'k, str>, f64>;
 
@@ -119,3 +154,31 @@ impl<'k> IntoIterator for FeatureList<'k> {
         self.0.into_iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_hashes_features() {
+        let list = vec![
+            ("list:near:rank", 1.0),
+            ("meta:tag:value:a", 1.0),
+            ("meta:tag:value:b", 1.0),
+            ("meta:tag:value:c", 1.0),
+        ]
+        .into_iter()
+        .collect::<FeatureList>();
+
+        let hashed = list.clone().hashed(2, "meta:");
+        assert_eq!(hashed.get("list:near:rank"), Some(&1.0));
+        assert!(hashed
+            .keys()
+            .all(|k| k == "list:near:rank" || k.starts_with("hash:")));
+        assert_eq!(hashed.values().sum::<f64>(), 4.0);
+        assert!(hashed.len() <= 3);
+
+        let again = list.hashed(2, "meta:");
+        assert_eq!(*hashed, *again);
+    }
+}
@@ -1,11 +1,18 @@
 pub use self::example::{BasicExample, Example, ListPosition};
+use self::format::Versioned;
 pub use self::list::FeatureList;
+pub use self::model::{Model, Scaling};
+pub use self::version::{ModelPointer, ModelVersion};
 use crate::storage::sealed::Sealed;
 use failure::Error;
+use std::collections::HashMap;
 use uuid::Uuid;
 
 mod example;
+pub(crate) mod format;
 mod list;
+mod model;
+mod version;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Activity {
@@ -14,19 +21,139 @@ pub struct Activity {
     pub current: Example,
     pub visible: Vec<Example>,
     pub chosen: Option<Vec<Uuid>>,
+    /// The type of event the items were chosen with (for example, a click or
+    /// a purchase), if it was given.
+    pub event: Option<String>,
+    /// When the activity happened, in milliseconds since the unix epoch.
+    pub created: u64,
+    /// How each of the visible examples was shown, in the same order.  This
+    /// is empty for activities that were stored before it was recorded.
+    pub impressions: Vec<Impression>,
+    /// Whether the part's model has already learned from the activity
+    /// online, so that it isn't learned from again when another item is
+    /// chosen.
+    pub learned: bool,
+}
+
+/// An activity, as it was stored before the format was versioned.
+#[derive(Deserialize)]
+struct LegacyActivity {
+    id: Uuid,
+    part: String,
+    current: Example,
+    visible: Vec<Example>,
+    chosen: Option<Vec<Uuid>>,
+}
+
+impl Versioned for Activity {
+    const VERSION: u32 = 1;
+
+    /// Activities stored before the format was versioned don't have an
+    /// event, a time, or impressions.
+    fn migrate(_: u32, data: &[u8]) -> Result<Activity, Error> {
+        let legacy = bincode::deserialize::<LegacyActivity>(data)?;
+        Ok(Activity {
+            id: legacy.id,
+            part: legacy.part,
+            current: legacy.current,
+            visible: legacy.visible,
+            chosen: legacy.chosen,
+            event: None,
+            created: 0,
+            impressions: vec![],
+            learned: false,
+        })
+    }
+}
+
+/// Where an example of an activity was shown, and how it got there.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Impression {
+    /// The position the example was shown at, starting from zero.
+    pub position: usize,
+    /// The score the example was given when it was shown.
+    pub score: f64,
+    /// The probability that the part's exploration policy put the example
+    /// at its position.
+    pub propensity: f64,
+}
+
+impl Activity {
+    /// How the visible example at the given index was shown, if it was
+    /// recorded.
+    pub fn impression(&self, index: usize) -> Option<&Impression> {
+        self.impressions.get(index)
+    }
+}
+
+/// How many times an item has been shown in a part, and how many of those
+/// times it was chosen.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemStats {
+    pub shown: u64,
+    pub chosen: u64,
+}
+
+/// Adds the items that were shown and chosen to the statistics of a part.
+pub(crate) fn record_stats(stats: &mut HashMap<Uuid, ItemStats>, shown: &[Uuid], chosen: &[Uuid]) {
+    for id in shown {
+        stats.entry(*id).or_default().shown += 1;
+    }
+    for id in chosen {
+        stats.entry(*id).or_default().chosen += 1;
+    }
 }
 
 pub trait ModelStore: Sealed {
-    fn set_default_model(&self, list: FeatureList<'_>) -> Result<(), Error>;
-    fn find_default_model(&self) -> Result<FeatureList<'static>, Error>;
-    fn find_model(&self, part: &str) -> Result<Option<FeatureList<'static>>, Error>;
+    fn set_default_model(&self, model: Model) -> Result<(), Error>;
+    fn find_default_model(&self) -> Result<Model, Error>;
+    fn set_model(&self, part: &str, model: Model) -> Result<(), Error>;
+    fn find_model(&self, part: &str) -> Result<Option<Model>, Error>;
+
+    fn model_version_save(&self, version: &ModelVersion) -> Result<(), Error>;
+    fn model_version_find(&self, part: &str, id: Uuid) -> Result<Option<ModelVersion>, Error>;
+    /// Lists the ids of all of the versions of the part, oldest first.
+    fn model_version_list(&self, part: &str) -> Result<Vec<Uuid>, Error>;
+    /// Deletes the version, and takes it off the list of the part's
+    /// versions.
+    fn model_version_delete(&self, part: &str, id: Uuid) -> Result<(), Error>;
+    fn model_pointer_find(&self, part: &str) -> Result<ModelPointer, Error>;
+    fn model_pointer_set(&self, part: &str, pointer: &ModelPointer) -> Result<(), Error>;
 
     fn model_activity_save(&self, part: &str, activity: &Activity) -> Result<(), Error>;
     fn model_activity_load(&self, part: &str, id: Uuid) -> Result<Option<Activity>, Error>;
-    fn model_activity_choose(&self, part: &str, id: Uuid, chosen: &[Uuid]) -> Result<(), Error>;
+    /// Marks the items as chosen in the activity, with the type of event
+    /// they were chosen with (if it's known).
+    fn model_activity_choose(
+        &self,
+        part: &str,
+        id: Uuid,
+        chosen: &[Uuid],
+        event: Option<&str>,
+    ) -> Result<(), Error>;
+
+    /// Marks the activity as learned from online, and returns it, unless it
+    /// had already been marked (or doesn't exist).
+    fn model_activity_learn(&self, part: &str, id: Uuid) -> Result<Option<Activity>, Error>;
+
+    /// Adds to the number of times each of the items has been shown in the
+    /// part, and the number of times each has been chosen.
+    fn model_stats_record(&self, part: &str, shown: &[Uuid], chosen: &[Uuid]) -> Result<(), Error>;
+    /// The number of times each of the items of the part has been shown and
+    /// chosen.  Items that have never been shown are left out.
+    fn model_stats_find(&self, part: &str) -> Result<HashMap<Uuid, ItemStats>, Error>;
 
     fn model_activity_pluck(&self) -> Result<Vec<Activity>, Error>;
+    fn model_activity_pluck_part(&self, part: &str) -> Result<Vec<Activity>, Error>;
+    /// The activities of the part that are waiting to be trained on, which
+    /// are left where they are.
+    fn model_activity_list_part(&self, part: &str) -> Result<Vec<Activity>, Error>;
     fn model_activity_delete_all<'p, Ids>(&self, id: Ids) -> Result<(), Error>
     where
         Ids: IntoIterator<Item = (&'p str, Uuid)>;
+    /// Puts activities that were plucked back on the list of activities
+    /// waiting to be trained on.
+    fn model_activity_restore<'p, Ids>(&self, ids: Ids) -> Result<(), Error>
+    where
+        Ids: IntoIterator<Item = (&'p str, Uuid)>;
 }
@@ -0,0 +1,141 @@
+use super::format::Versioned;
+use crate::learn::calibration::Calibration;
+use crate::storage::FeatureList;
+use failure::Error;
+use std::collections::HashMap;
+
+/// A trained model, as it is stored and served for a part.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Model {
+    /// The linear weight of each feature.
+    pub weights: FeatureList<'static>,
+    /// The latent factors of each feature.  These are only used by models
+    /// trained as factorization machines; for any other model, this is
+    /// empty.
+    pub factors: HashMap<String, Vec<f64>>,
+    /// The log-odds that are added to every prediction the model makes.
+    /// This corrects for the negative examples that were left out when the
+    /// model was trained, so that its predictions are still probabilities.
+    pub offset: f64,
+    /// How the value of each feature is scaled before it's given to the
+    /// model.  Features without a scaling are given as they are.
+    pub scaling: HashMap<String, Scaling>,
+    /// How the model's predictions are mapped to probabilities.
+    pub calibration: Calibration<f64>,
+    /// The fraction of the examples the model was trained on that had each
+    /// feature, with a non-zero value.
+    pub coverage: HashMap<String, f64>,
+}
+
+/// How the values of a feature are transformed before they're used by a
+/// model: the shift is subtracted from the value, which is then divided by
+/// the scale.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scaling {
+    pub shift: f64,
+    pub scale: f64,
+}
+
+impl Scaling {
+    pub fn apply(&self, value: f64) -> f64 {
+        if self.scale > 0.0 {
+            (value - self.shift) / self.scale
+        } else {
+            value - self.shift
+        }
+    }
+
+    /// Scales each of the features that has a scaling.
+    pub fn apply_all(
+        scaling: &HashMap<String, Scaling>,
+        features: FeatureList<'static>,
+    ) -> FeatureList<'static> {
+        if scaling.is_empty() {
+            return features;
+        }
+
+        features
+            .into_iter()
+            .map(|(key, value)| match scaling.get(key.as_ref()) {
+                Some(scaling) => (key, scaling.apply(value)),
+                None => (key, value),
+            })
+            .collect()
+    }
+}
+
+impl Model {
+    /// A model with only linear weights.
+    pub fn linear(weights: FeatureList<'static>) -> Model {
+        Model {
+            weights,
+            factors: HashMap::new(),
+            offset: 0.0,
+            scaling: HashMap::new(),
+            calibration: Calibration::Identity,
+            coverage: HashMap::new(),
+        }
+    }
+
+    /// Scales the features the way the model was trained on them.
+    pub fn scale(&self, features: FeatureList<'static>) -> FeatureList<'static> {
+        Scaling::apply_all(&self.scaling, features)
+    }
+
+    /// The weight of a feature, for its value before it's scaled.  This is
+    /// what the weights of models with different scalings are compared by.
+    pub fn unscaled_weight(&self, feature: &str, weight: f64) -> f64 {
+        match self.scaling.get(feature) {
+            Some(scaling) if scaling.scale > 0.0 => weight / scaling.scale,
+            _ => weight,
+        }
+    }
+
+    /// Applies the model's offset and calibration to one of its
+    /// predictions.  The calibration is fit on the offset predictions of
+    /// examples that weren't downsampled, so it's applied after the offset.
+    pub fn correct(&self, prediction: f64) -> f64 {
+        self.calibration
+            .apply(Model::offset_prediction(prediction, self.offset))
+    }
+
+    /// Shifts the log-odds of a prediction by the offset.
+    pub fn offset_prediction(prediction: f64, offset: f64) -> f64 {
+        if offset == 0.0 {
+            return prediction;
+        }
+
+        let odds = offset.exp();
+        prediction * odds / (prediction * odds + 1.0 - prediction)
+    }
+}
+
+impl Versioned for Model {
+    const VERSION: u32 = 1;
+
+    /// Before the format was versioned, a model was only its linear
+    /// weights.
+    fn migrate(_: u32, data: &[u8]) -> Result<Model, Error> {
+        Ok(Model::linear(bincode::deserialize(data)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learn::logistic::sigmoid;
+
+    #[test]
+    fn it_corrects_predictions_by_the_offset() {
+        let model = Model {
+            offset: 0.1f64.ln(),
+            ..Default::default()
+        };
+        for raw in &[-2.0, 0.0, 1.5] {
+            let expected = sigmoid(raw + model.offset);
+            assert!((model.correct(sigmoid(*raw)) - expected).abs() < 1e-12);
+        }
+        assert_eq!(Model::default().correct(0.25), 0.25);
+    }
+}
@@ -0,0 +1,93 @@
+use super::format::Versioned;
+use crate::learn::{Convergence, Parameters};
+use crate::storage::Model;
+use failure::{format_err, Error};
+use uuid::Uuid;
+
+/// A single trained model.  Versions are immutable once they've been
+/// saved; which version is actually used for a part is decided by that
+/// part's [`ModelPointer`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ModelVersion {
+    pub id: Uuid,
+    pub part: String,
+    /// When the version was trained, in milliseconds since the unix epoch.
+    pub created: u64,
+    /// The number of examples the version was trained and evaluated on.
+    pub examples: usize,
+    /// The performance of the version on the holdout set.
+    pub performance: f64,
+    pub parameters: Parameters<f64>,
+    pub model: Model,
+    /// How training the version went.
+    pub convergence: Convergence<f64>,
+}
+
+impl ModelVersion {
+    pub fn new(
+        part: &str,
+        examples: usize,
+        performance: f64,
+        parameters: Parameters<f64>,
+        model: Model,
+        convergence: Convergence<f64>,
+    ) -> ModelVersion {
+        let created = std::time::UNIX_EPOCH
+            .elapsed()
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        ModelVersion {
+            id: Uuid::new_v4(),
+            part: part.to_owned(),
+            created,
+            examples,
+            performance,
+            parameters,
+            model,
+            convergence,
+        }
+    }
+}
+
+impl Versioned for ModelVersion {
+    const VERSION: u32 = 1;
+
+    /// Versions have always been stored with the version of their format.
+    fn migrate(version: u32, _: &[u8]) -> Result<ModelVersion, Error> {
+        Err(format_err!("unknown model version format {}", version))
+    }
+}
+
+/// Points to the version of the model that is currently active for a part.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ModelPointer {
+    pub active: Option<Uuid>,
+    /// Whether or not the active version was pinned.  Pinned versions are
+    /// not replaced by newly trained versions.
+    pub pinned: bool,
+    /// The versions that were previously active, the most recent last.  This
+    /// is used to roll back to a previous version.
+    pub history: Vec<Uuid>,
+}
+
+impl ModelPointer {
+    /// Makes the given version the active one, remembering the previously
+    /// active version so that it can be rolled back to.
+    pub fn activate(&mut self, id: Uuid) {
+        if let Some(previous) = self.active.replace(id) {
+            if previous != id {
+                self.history.push(previous);
+            }
+        }
+    }
+
+    /// Reactivates the previously active version, if there is one.
+    pub fn rollback(&mut self) -> Option<Uuid> {
+        let previous = self.history.pop()?;
+        self.active = Some(previous);
+        Some(previous)
+    }
+}
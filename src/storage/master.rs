@@ -5,10 +5,14 @@ use super::mem::MemStorage;
 use super::redis::RedisStorage;
 #[cfg(feature = "aerospike")]
 use super::spike::SpikeStorage;
-use super::{Activity, FeatureList, ItemStore, ModelStore, Sealed, Store, UserData, UserStore};
+use super::{
+    Activity, ItemStats, ItemStore, Model, ModelPointer, ModelStore, ModelVersion, Sealed, Store,
+    UserData, UserStore,
+};
 use config::Config;
 
 use failure::Error;
+use std::collections::HashMap;
 use uuid::Uuid;
 #[derive(Debug)]
 pub enum MasterStorage {
@@ -69,18 +73,46 @@ impl UserStore for MasterStorage {
 
 #[allow(unused_variables)]
 impl ModelStore for MasterStorage {
-    fn set_default_model(&self, list: FeatureList) -> Result<(), Error> {
-        expand_storage!(self, storage, storage.set_default_model(list))
+    fn set_default_model(&self, model: Model) -> Result<(), Error> {
+        expand_storage!(self, storage, storage.set_default_model(model))
     }
 
-    fn find_default_model(&self) -> Result<FeatureList<'static>, Error> {
+    fn find_default_model(&self) -> Result<Model, Error> {
         expand_storage!(self, storage, storage.find_default_model())
     }
 
-    fn find_model(&self, part: &str) -> Result<Option<FeatureList<'static>>, Error> {
+    fn set_model(&self, part: &str, model: Model) -> Result<(), Error> {
+        expand_storage!(self, storage, storage.set_model(part, model))
+    }
+
+    fn find_model(&self, part: &str) -> Result<Option<Model>, Error> {
         expand_storage!(self, storage, storage.find_model(part))
     }
 
+    fn model_version_save(&self, version: &ModelVersion) -> Result<(), Error> {
+        expand_storage!(self, storage, storage.model_version_save(version))
+    }
+
+    fn model_version_find(&self, part: &str, id: Uuid) -> Result<Option<ModelVersion>, Error> {
+        expand_storage!(self, storage, storage.model_version_find(part, id))
+    }
+
+    fn model_version_list(&self, part: &str) -> Result<Vec<Uuid>, Error> {
+        expand_storage!(self, storage, storage.model_version_list(part))
+    }
+
+    fn model_version_delete(&self, part: &str, id: Uuid) -> Result<(), Error> {
+        expand_storage!(self, storage, storage.model_version_delete(part, id))
+    }
+
+    fn model_pointer_find(&self, part: &str) -> Result<ModelPointer, Error> {
+        expand_storage!(self, storage, storage.model_pointer_find(part))
+    }
+
+    fn model_pointer_set(&self, part: &str, pointer: &ModelPointer) -> Result<(), Error> {
+        expand_storage!(self, storage, storage.model_pointer_set(part, pointer))
+    }
+
     fn model_activity_save(&self, part: &str, activity: &Activity) -> Result<(), Error> {
         expand_storage!(self, storage, storage.model_activity_save(part, activity))
     }
@@ -89,24 +121,61 @@ impl ModelStore for MasterStorage {
         expand_storage!(self, storage, storage.model_activity_load(part, id))
     }
 
-    fn model_activity_choose(&self, part: &str, id: Uuid, chosen: &[Uuid]) -> Result<(), Error> {
+    fn model_activity_choose(
+        &self,
+        part: &str,
+        id: Uuid,
+        chosen: &[Uuid],
+        event: Option<&str>,
+    ) -> Result<(), Error> {
         expand_storage!(
             self,
             storage,
-            storage.model_activity_choose(part, id, chosen)
+            storage.model_activity_choose(part, id, chosen, event)
         )
     }
 
+    fn model_activity_learn(&self, part: &str, id: Uuid) -> Result<Option<Activity>, Error> {
+        expand_storage!(self, storage, storage.model_activity_learn(part, id))
+    }
+
+    fn model_stats_record(&self, part: &str, shown: &[Uuid], chosen: &[Uuid]) -> Result<(), Error> {
+        expand_storage!(
+            self,
+            storage,
+            storage.model_stats_record(part, shown, chosen)
+        )
+    }
+
+    fn model_stats_find(&self, part: &str) -> Result<HashMap<Uuid, ItemStats>, Error> {
+        expand_storage!(self, storage, storage.model_stats_find(part))
+    }
+
     fn model_activity_pluck(&self) -> Result<Vec<Activity>, Error> {
         expand_storage!(self, storage, storage.model_activity_pluck())
     }
 
+    fn model_activity_pluck_part(&self, part: &str) -> Result<Vec<Activity>, Error> {
+        expand_storage!(self, storage, storage.model_activity_pluck_part(part))
+    }
+
+    fn model_activity_list_part(&self, part: &str) -> Result<Vec<Activity>, Error> {
+        expand_storage!(self, storage, storage.model_activity_list_part(part))
+    }
+
     fn model_activity_delete_all<'p, Ids>(&self, id: Ids) -> Result<(), Error>
     where
         Ids: IntoIterator<Item = (&'p str, Uuid)>,
     {
         expand_storage!(self, storage, storage.model_activity_delete_all(id))
     }
+
+    fn model_activity_restore<'p, Ids>(&self, id: Ids) -> Result<(), Error>
+    where
+        Ids: IntoIterator<Item = (&'p str, Uuid)>,
+    {
+        expand_storage!(self, storage, storage.model_activity_restore(id))
+    }
 }
 
 #[allow(unused_variables)]
@@ -1,5 +1,6 @@
+use crate::storage::core::models::format::{self, Versioned};
 use failure::Error;
-use lmdb::{Database, RwTransaction, Transaction};
+use lmdb::{Database, RwTransaction, Transaction, WriteFlags};
 use serde::{Deserialize, Serialize};
 
 pub trait ResultExt<T, E> {
@@ -20,12 +21,23 @@ pub trait TransactionExt {
     fn deget<'s, T: Deserialize<'s>, K>(&'s self, db: Database, key: K) -> Result<Option<T>, Error>
     where
         K: AsRef<[u8]>;
+    fn deget_versioned<T: Versioned, K>(&self, db: Database, key: K) -> Result<Option<T>, Error>
+    where
+        K: AsRef<[u8]>;
 }
 
 pub trait WriteTransactionExt {
     fn serput<T: Serialize, K>(&mut self, db: Database, key: K, data: &T) -> Result<(), Error>
     where
         K: AsRef<[u8]>;
+    fn serput_versioned<T: Versioned, K>(
+        &mut self,
+        db: Database,
+        key: K,
+        data: &T,
+    ) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>;
 }
 
 impl<T> TransactionExt for T
@@ -42,6 +54,16 @@ where
             .transpose()
             .map_err(Error::from)
     }
+
+    fn deget_versioned<D: Versioned, K>(&self, db: Database, key: K) -> Result<Option<D>, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.get(db, &key)
+            .optional()?
+            .map(format::decode)
+            .transpose()
+    }
 }
 impl WriteTransactionExt for RwTransaction<'_> {
     fn serput<T: Serialize, K>(&mut self, db: Database, key: K, data: &T) -> Result<(), Error>
@@ -54,4 +76,18 @@ impl WriteTransactionExt for RwTransaction<'_> {
         bincode::serialize_into(writer, data)?;
         Ok(())
     }
+
+    fn serput_versioned<T: Versioned, K>(
+        &mut self,
+        db: Database,
+        key: K,
+        data: &T,
+    ) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        let data = format::encode(data)?;
+        self.put(db, &key, &data, WriteFlags::empty())?;
+        Ok(())
+    }
 }
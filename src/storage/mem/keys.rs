@@ -71,6 +71,26 @@ impl Keys {
         "model:default".to_owned()
     }
 
+    pub(super) fn model_version_key(&self, part: &str, id: Uuid) -> String {
+        format!("model:version:{}:{}", part, id)
+    }
+
+    pub(super) fn model_version_list_key(&self, part: &str) -> String {
+        format!("model:version-list:{}", part)
+    }
+
+    pub(super) fn model_pointer_key(&self, part: &str) -> String {
+        format!("model:pointer:{}", part)
+    }
+
+    pub(super) fn model_stats_key(&self, part: &str, id: Uuid) -> String {
+        format!("model:stats:{}:{}", part, id)
+    }
+
+    pub(super) fn model_stats_list_key(&self, part: &str) -> String {
+        format!("model:stats-list:{}", part)
+    }
+
     pub(super) fn activity_key(&self, part: &str, id: Uuid) -> String {
         format!("activity:item:{}:{}", part, id)
     }
@@ -3,38 +3,106 @@ use super::MemStorage;
 use crate::storage::core::models::*;
 use failure::Error;
 use lmdb::{Database, RwTransaction};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use uuid::Uuid;
 
 impl ModelStore for MemStorage {
-    fn set_default_model(&self, list: FeatureList) -> Result<(), Error> {
+    fn set_default_model(&self, model: Model) -> Result<(), Error> {
         self.write_transaction(self.keys.model_database(), |txn, db| {
             let key = self.keys.default_model_key();
-            txn.serput(db, &key, &list)?;
+            txn.serput_versioned(db, &key, &model)?;
             Ok(())
         })
     }
 
-    fn find_default_model(&self) -> Result<FeatureList<'static>, Error> {
+    fn find_default_model(&self) -> Result<Model, Error> {
         self.read_transaction(self.keys.model_database(), |txn, db| {
             let key = self.keys.default_model_key();
-            let list = txn.deget(db, &key)?;
+            let list = txn.deget_versioned(db, &key)?;
             Ok(list.unwrap_or_default())
         })
     }
 
-    fn find_model(&self, part: &str) -> Result<Option<FeatureList<'static>>, Error> {
+    fn set_model(&self, part: &str, model: Model) -> Result<(), Error> {
+        self.write_transaction(self.keys.model_database(), |txn, db| {
+            let key = self.keys.model_key(part);
+            txn.serput_versioned(db, &key, &model)?;
+            Ok(())
+        })
+    }
+
+    fn find_model(&self, part: &str) -> Result<Option<Model>, Error> {
         self.read_transaction(self.keys.model_database(), |txn, db| {
             let key = self.keys.model_key(part);
-            let list = txn.deget(db, &key)?;
+            let list = txn.deget_versioned(db, &key)?;
             Ok(list)
         })
     }
 
+    fn model_version_save(&self, version: &ModelVersion) -> Result<(), Error> {
+        self.write_transaction(self.keys.model_database(), |txn, db| {
+            let key = self.keys.model_version_key(&version.part, version.id);
+            txn.serput_versioned(db, &key, version)?;
+            let key = self.keys.model_version_list_key(&version.part);
+            let mut list = txn.deget::<Vec<Uuid>, _>(db, &key)?.unwrap_or_default();
+            list.push(version.id);
+            txn.serput(db, &key, &list)?;
+            Ok(())
+        })
+    }
+
+    fn model_version_find(&self, part: &str, id: Uuid) -> Result<Option<ModelVersion>, Error> {
+        self.read_transaction(self.keys.model_database(), |txn, db| {
+            let key = self.keys.model_version_key(part, id);
+            txn.deget_versioned(db, &key)
+        })
+    }
+
+    fn model_version_list(&self, part: &str) -> Result<Vec<Uuid>, Error> {
+        self.read_transaction(self.keys.model_database(), |txn, db| {
+            let key = self.keys.model_version_list_key(part);
+            let list = txn.deget(db, &key)?;
+            Ok(list.unwrap_or_default())
+        })
+    }
+
+    fn model_version_delete(&self, part: &str, id: Uuid) -> Result<(), Error> {
+        self.write_transaction(self.keys.model_database(), |txn, db| {
+            let key = self.keys.model_version_key(part, id);
+            match txn.del(db, &key, None) {
+                Ok(_) => {}
+                // We don't care if it's not found.
+                Err(lmdb::Error::NotFound) => {}
+                Err(e) => Err(e)?,
+            }
+            let key = self.keys.model_version_list_key(part);
+            let mut list = txn.deget::<Vec<Uuid>, _>(db, &key)?.unwrap_or_default();
+            list.retain(|version| *version != id);
+            txn.serput(db, &key, &list)?;
+            Ok(())
+        })
+    }
+
+    fn model_pointer_find(&self, part: &str) -> Result<ModelPointer, Error> {
+        self.read_transaction(self.keys.model_database(), |txn, db| {
+            let key = self.keys.model_pointer_key(part);
+            let pointer = txn.deget(db, &key)?;
+            Ok(pointer.unwrap_or_default())
+        })
+    }
+
+    fn model_pointer_set(&self, part: &str, pointer: &ModelPointer) -> Result<(), Error> {
+        self.write_transaction(self.keys.model_database(), |txn, db| {
+            let key = self.keys.model_pointer_key(part);
+            txn.serput(db, &key, pointer)?;
+            Ok(())
+        })
+    }
+
     fn model_activity_save(&self, part: &str, activity: &Activity) -> Result<(), Error> {
         self.write_transaction(self.keys.model_database(), |txn, db| {
             let key = self.keys.activity_key(part, activity.id);
-            txn.serput(db, &key, activity)?;
+            txn.serput_versioned(db, &key, activity)?;
             push_activity(
                 txn,
                 db,
@@ -50,17 +118,24 @@ impl ModelStore for MemStorage {
     fn model_activity_load(&self, part: &str, id: Uuid) -> Result<Option<Activity>, Error> {
         self.read_transaction(self.keys.model_database(), |txn, db| {
             let key = self.keys.activity_key(part, id);
-            txn.deget(db, &key)
+            txn.deget_versioned(db, &key)
         })
     }
 
-    fn model_activity_choose(&self, part: &str, id: Uuid, chosen: &[Uuid]) -> Result<(), Error> {
+    fn model_activity_choose(
+        &self,
+        part: &str,
+        id: Uuid,
+        chosen: &[Uuid],
+        event: Option<&str>,
+    ) -> Result<(), Error> {
         self.write_transaction(self.keys.model_database(), |txn, db| {
             let key = self.keys.activity_key(part, id);
-            let item = txn.deget::<Activity, _>(db, &key)?;
+            let item = txn.deget_versioned::<Activity, _>(db, &key)?;
             if let Some(mut item) = item {
                 item.chosen = Some(chosen.to_owned());
-                txn.serput(db, &key, &item)?;
+                item.event = event.map(ToOwned::to_owned);
+                txn.serput_versioned(db, &key, &item)?;
                 Ok(())
             } else {
                 Ok(())
@@ -68,6 +143,67 @@ impl ModelStore for MemStorage {
         })
     }
 
+    fn model_activity_learn(&self, part: &str, id: Uuid) -> Result<Option<Activity>, Error> {
+        self.write_transaction(self.keys.model_database(), |txn, db| {
+            let key = self.keys.activity_key(part, id);
+            match txn.deget_versioned::<Activity, _>(db, &key)? {
+                Some(mut item) if !item.learned => {
+                    item.learned = true;
+                    txn.serput_versioned(db, &key, &item)?;
+                    Ok(Some(item))
+                }
+                _ => Ok(None),
+            }
+        })
+    }
+
+    fn model_stats_record(&self, part: &str, shown: &[Uuid], chosen: &[Uuid]) -> Result<(), Error> {
+        // Each item's stats are kept under their own key, so that only the
+        // items that were shown are rewritten.  The list of the items with
+        // stats is only rewritten when an item is shown for the first time.
+        let mut stats = HashMap::new();
+        record_stats(&mut stats, shown, chosen);
+
+        self.write_transaction(self.keys.model_database(), |txn, db| {
+            let mut added = vec![];
+            for (id, recorded) in stats {
+                let key = self.keys.model_stats_key(part, id);
+                let mut item = match txn.deget::<ItemStats, _>(db, &key)? {
+                    Some(item) => item,
+                    None => {
+                        added.push(id);
+                        ItemStats::default()
+                    }
+                };
+                item.shown += recorded.shown;
+                item.chosen += recorded.chosen;
+                txn.serput(db, &key, &item)?;
+            }
+
+            if !added.is_empty() {
+                let key = self.keys.model_stats_list_key(part);
+                let mut list = txn.deget::<Vec<Uuid>, _>(db, &key)?.unwrap_or_default();
+                list.extend(added);
+                txn.serput(db, &key, &list)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn model_stats_find(&self, part: &str) -> Result<HashMap<Uuid, ItemStats>, Error> {
+        self.read_transaction(self.keys.model_database(), |txn, db| {
+            let key = self.keys.model_stats_list_key(part);
+            let list = txn.deget::<Vec<Uuid>, _>(db, &key)?.unwrap_or_default();
+            let mut stats = HashMap::new();
+            for id in list {
+                if let Some(item) = txn.deget(db, self.keys.model_stats_key(part, id))? {
+                    stats.insert(id, item);
+                }
+            }
+            Ok(stats)
+        })
+    }
+
     fn model_activity_pluck(&self) -> Result<Vec<Activity>, Error> {
         self.write_transaction(self.keys.model_database(), |txn, db| {
             let key = self.keys.default_activity_list_key();
@@ -78,7 +214,46 @@ impl ModelStore for MemStorage {
             let result = result
                 .into_iter()
                 .flat_map(|(part, id)| {
-                    txn.deget::<Activity, _>(db, self.keys.activity_key(&part, id))
+                    txn.deget_versioned::<Activity, _>(db, self.keys.activity_key(&part, id))
+                        .unwrap_or_default()
+                })
+                .collect::<Vec<_>>();
+
+            Ok(result)
+        })
+    }
+
+    fn model_activity_pluck_part(&self, part: &str) -> Result<Vec<Activity>, Error> {
+        self.write_transaction(self.keys.model_database(), |txn, db| {
+            let key = self.keys.default_activity_list_key();
+            let (result, rest) = txn
+                .deget::<Vec<(String, Uuid)>, _>(db, &key)?
+                .unwrap_or_default()
+                .into_iter()
+                .partition::<Vec<_>, _>(|(p, _)| p == part);
+            txn.serput(db, &key, &rest)?;
+            let result = result
+                .into_iter()
+                .flat_map(|(part, id)| {
+                    txn.deget_versioned::<Activity, _>(db, self.keys.activity_key(&part, id))
+                        .unwrap_or_default()
+                })
+                .collect::<Vec<_>>();
+
+            Ok(result)
+        })
+    }
+
+    fn model_activity_list_part(&self, part: &str) -> Result<Vec<Activity>, Error> {
+        self.read_transaction(self.keys.model_database(), |txn, db| {
+            let key = self.keys.default_activity_list_key();
+            let result = txn
+                .deget::<Vec<(String, Uuid)>, _>(db, &key)?
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(p, _)| p == part)
+                .flat_map(|(part, id)| {
+                    txn.deget_versioned::<Activity, _>(db, self.keys.activity_key(&part, id))
                         .unwrap_or_default()
                 })
                 .collect::<Vec<_>>();
@@ -105,6 +280,20 @@ impl ModelStore for MemStorage {
             Ok(())
         })
     }
+
+    fn model_activity_restore<'p, Ids>(&self, ids: Ids) -> Result<(), Error>
+    where
+        Ids: IntoIterator<Item = (&'p str, Uuid)>,
+    {
+        self.write_transaction(self.keys.model_database(), |txn, db| {
+            let key = self.keys.default_activity_list_key();
+            for (part, id) in ids.into_iter() {
+                push_activity(txn, db, &key, self.activity_list_length, part, id)?;
+            }
+
+            Ok(())
+        })
+    }
 }
 
 fn push_activity(
@@ -1,4 +1,6 @@
-use crate::storage::{Item, ItemStore};
+use crate::storage::{
+    Activity, BasicExample, Example, FeatureList, Item, ItemStats, ItemStore, Model, ModelStore,
+};
 use rand::seq::SliceRandom;
 use uuid::Uuid;
 
@@ -86,3 +88,109 @@ fn it_calculates_near_items() {
     // the list _and_ have the highest value.
     assert_eq!(list.items[0].0, items[0].id);
 }
+
+#[test]
+fn it_stores_part_models() {
+    let storage = TemporaryFileWrap::load();
+    let mut list = FeatureList::default();
+    list.insert("list:near:rank", 0.5);
+    let mut model = Model::linear(list);
+    model
+        .factors
+        .insert("list:near:rank".to_string(), vec![0.25, -0.25]);
+    storage
+        .set_model("articles", model)
+        .expect("could not set model");
+
+    let model = storage
+        .find_model("articles")
+        .expect("could not load model")
+        .expect("model not found");
+    assert_eq!(model.weights.get("list:near:rank"), Some(&0.5));
+    assert_eq!(model.factors["list:near:rank"], vec![0.25, -0.25]);
+    let other = storage
+        .find_model("products")
+        .expect("could not load model");
+    assert!(other.is_none());
+}
+
+fn create_activity(part: &str) -> Activity {
+    let current = create_item();
+    let visible = create_item();
+    Activity {
+        id: Uuid::new_v4(),
+        part: part.to_string(),
+        current: Example::new(BasicExample::new(current.id), current),
+        visible: vec![Example::new(BasicExample::new(visible.id), visible)],
+        chosen: None,
+        event: None,
+        created: 0,
+        impressions: vec![],
+        learned: false,
+    }
+}
+
+#[test]
+fn it_plucks_activities_by_part() {
+    let storage = TemporaryFileWrap::load();
+    let articles = create_activity("articles");
+    let products = create_activity("products");
+    storage
+        .model_activity_save(&articles.part, &articles)
+        .expect("could not save activity");
+    storage
+        .model_activity_save(&products.part, &products)
+        .expect("could not save activity");
+
+    let listed = storage
+        .model_activity_list_part("articles")
+        .expect("could not list activities");
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, articles.id);
+
+    let plucked = storage
+        .model_activity_pluck_part("articles")
+        .expect("could not pluck activities");
+    assert_eq!(plucked.len(), 1);
+    assert_eq!(plucked[0].id, articles.id);
+
+    let rest = storage
+        .model_activity_pluck()
+        .expect("could not pluck activities");
+    assert_eq!(rest.len(), 1);
+    assert_eq!(rest[0].id, products.id);
+}
+
+#[test]
+fn it_records_item_stats() {
+    let storage = TemporaryFileWrap::load();
+    let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+    storage
+        .model_stats_record("articles", &[first, second], &[])
+        .expect("could not record stats");
+    storage
+        .model_stats_record("articles", &[first], &[first])
+        .expect("could not record stats");
+
+    let stats = storage
+        .model_stats_find("articles")
+        .expect("could not load stats");
+    assert_eq!(
+        stats[&first],
+        ItemStats {
+            shown: 2,
+            chosen: 1
+        }
+    );
+    assert_eq!(
+        stats[&second],
+        ItemStats {
+            shown: 1,
+            chosen: 0
+        }
+    );
+    let other = storage
+        .model_stats_find("products")
+        .expect("could not load stats");
+    assert!(other.is_empty());
+}
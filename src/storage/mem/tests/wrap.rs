@@ -1,12 +1,13 @@
 use crate::storage::mem::{MemStorage, MemStorageConfiguration};
 use crate::storage::sealed::Sealed;
 use crate::storage::{
-    Activity, FeatureList, Item, ItemList, ItemStore, ModelStore, Store, TimeScope, UserData,
-    UserStore,
+    Activity, Item, ItemList, ItemStats, ItemStore, Model, ModelPointer, ModelStore, ModelVersion,
+    Store, TimeScope, UserData, UserStore,
 };
 use failure::Error;
 use rand::distributions::Alphanumeric;
 use rand::Rng;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use uuid::Uuid;
 
@@ -112,18 +113,46 @@ impl<T: Store> ItemStore for TemporaryFileWrap<T> {
 }
 
 impl<T: Store> ModelStore for TemporaryFileWrap<T> {
-    fn set_default_model(&self, list: FeatureList<'_>) -> Result<(), Error> {
-        self.0.set_default_model(list)
+    fn set_default_model(&self, model: Model) -> Result<(), Error> {
+        self.0.set_default_model(model)
     }
 
-    fn find_default_model(&self) -> Result<FeatureList<'static>, Error> {
+    fn find_default_model(&self) -> Result<Model, Error> {
         self.0.find_default_model()
     }
 
-    fn find_model(&self, part: &str) -> Result<Option<FeatureList<'static>>, Error> {
+    fn set_model(&self, part: &str, model: Model) -> Result<(), Error> {
+        self.0.set_model(part, model)
+    }
+
+    fn find_model(&self, part: &str) -> Result<Option<Model>, Error> {
         self.0.find_model(part)
     }
 
+    fn model_version_save(&self, version: &ModelVersion) -> Result<(), Error> {
+        self.0.model_version_save(version)
+    }
+
+    fn model_version_find(&self, part: &str, id: Uuid) -> Result<Option<ModelVersion>, Error> {
+        self.0.model_version_find(part, id)
+    }
+
+    fn model_version_list(&self, part: &str) -> Result<Vec<Uuid>, Error> {
+        self.0.model_version_list(part)
+    }
+
+    fn model_version_delete(&self, part: &str, id: Uuid) -> Result<(), Error> {
+        self.0.model_version_delete(part, id)
+    }
+
+    fn model_pointer_find(&self, part: &str) -> Result<ModelPointer, Error> {
+        self.0.model_pointer_find(part)
+    }
+
+    fn model_pointer_set(&self, part: &str, pointer: &ModelPointer) -> Result<(), Error> {
+        self.0.model_pointer_set(part, pointer)
+    }
+
     fn model_activity_save(&self, part: &str, activity: &Activity) -> Result<(), Error> {
         self.0.model_activity_save(part, activity)
     }
@@ -132,20 +161,53 @@ impl<T: Store> ModelStore for TemporaryFileWrap<T> {
         self.0.model_activity_load(part, id)
     }
 
-    fn model_activity_choose(&self, part: &str, id: Uuid, chosen: &[Uuid]) -> Result<(), Error> {
-        self.0.model_activity_choose(part, id, chosen)
+    fn model_activity_choose(
+        &self,
+        part: &str,
+        id: Uuid,
+        chosen: &[Uuid],
+        event: Option<&str>,
+    ) -> Result<(), Error> {
+        self.0.model_activity_choose(part, id, chosen, event)
+    }
+
+    fn model_activity_learn(&self, part: &str, id: Uuid) -> Result<Option<Activity>, Error> {
+        self.0.model_activity_learn(part, id)
+    }
+
+    fn model_stats_record(&self, part: &str, shown: &[Uuid], chosen: &[Uuid]) -> Result<(), Error> {
+        self.0.model_stats_record(part, shown, chosen)
+    }
+
+    fn model_stats_find(&self, part: &str) -> Result<HashMap<Uuid, ItemStats>, Error> {
+        self.0.model_stats_find(part)
     }
 
     fn model_activity_pluck(&self) -> Result<Vec<Activity>, Error> {
         self.0.model_activity_pluck()
     }
 
+    fn model_activity_pluck_part(&self, part: &str) -> Result<Vec<Activity>, Error> {
+        self.0.model_activity_pluck_part(part)
+    }
+
+    fn model_activity_list_part(&self, part: &str) -> Result<Vec<Activity>, Error> {
+        self.0.model_activity_list_part(part)
+    }
+
     fn model_activity_delete_all<'p, Ids>(&self, id: Ids) -> Result<(), Error>
     where
         Ids: IntoIterator<Item = (&'p str, Uuid)>,
     {
         self.0.model_activity_delete_all(id)
     }
+
+    fn model_activity_restore<'p, Ids>(&self, id: Ids) -> Result<(), Error>
+    where
+        Ids: IntoIterator<Item = (&'p str, Uuid)>,
+    {
+        self.0.model_activity_restore(id)
+    }
 }
 
 impl<T: Store> UserStore for TemporaryFileWrap<T> {
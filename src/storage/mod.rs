@@ -1,5 +1,8 @@
 pub use self::core::items::{Item, ItemList, ItemListDecay, ItemStore, NearListDecay, TimeScope};
-pub use self::core::models::{Activity, BasicExample, Example, FeatureList, ModelStore};
+pub use self::core::models::{
+    Activity, BasicExample, Example, FeatureList, Impression, ItemStats, Model, ModelPointer,
+    ModelStore, ModelVersion, Scaling,
+};
 pub use self::core::users::{UserData, UserStore};
 use self::sealed::Sealed;
 
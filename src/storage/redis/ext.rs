@@ -1,3 +1,4 @@
+use crate::storage::core::models::format::{self, Versioned};
 use failure::Error;
 use redis::ToRedisArgs;
 use serde::de::DeserializeOwned;
@@ -10,6 +11,12 @@ pub trait CommandsExt {
     fn serput<T: Serialize, K>(&mut self, key: K, data: &T) -> Result<(), Error>
     where
         K: ToRedisArgs;
+    fn deget_versioned<T: Versioned, K>(&mut self, key: K) -> Result<Option<T>, Error>
+    where
+        K: ToRedisArgs;
+    fn serput_versioned<T: Versioned, K>(&mut self, key: K, data: &T) -> Result<(), Error>
+    where
+        K: ToRedisArgs;
 }
 
 impl<C: redis::Commands> CommandsExt for C {
@@ -33,4 +40,23 @@ impl<C: redis::Commands> CommandsExt for C {
         let _: () = self.set(key, data)?;
         Ok(())
     }
+
+    fn deget_versioned<T: Versioned, K>(&mut self, key: K) -> Result<Option<T>, Error>
+    where
+        K: ToRedisArgs,
+    {
+        self.get::<_, Option<Vec<u8>>>(key)?
+            .as_deref()
+            .map(format::decode)
+            .transpose()
+    }
+
+    fn serput_versioned<T: Versioned, K>(&mut self, key: K, data: &T) -> Result<(), Error>
+    where
+        K: ToRedisArgs,
+    {
+        let data = format::encode(data)?;
+        let _: () = self.set(key, data)?;
+        Ok(())
+    }
 }
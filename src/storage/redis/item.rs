@@ -100,7 +100,7 @@ impl ItemStore for RedisStorage {
     fn items_delete(&self, part: &str, id: Uuid) -> Result<(), Error> {
         let mut conn = self.client.get_connection()?;
         let item_key = self.keys.item_key(&part, id);
-        conn.del(item_key)?;
+        let _: () = conn.del(item_key)?;
         Ok(())
     }
 
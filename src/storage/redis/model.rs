@@ -1,34 +1,94 @@
 use super::ext::*;
 use super::RedisStorage;
-use crate::storage::{Activity, FeatureList, ModelStore};
+use crate::storage::core::models::format;
+use crate::storage::{Activity, ItemStats, Model, ModelPointer, ModelStore, ModelVersion};
 use failure::Error;
 use redis::{Commands, Connection, PipelineCommands};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 impl ModelStore for RedisStorage {
-    fn set_default_model(&self, list: FeatureList<'_>) -> Result<(), Error> {
+    fn set_default_model(&self, model: Model) -> Result<(), Error> {
         let mut conn = self.client.get_connection()?;
         let key = self.keys.default_model_key();
-        conn.serput(key, &list)?;
+        conn.serput_versioned(key, &model)?;
         Ok(())
     }
 
-    fn find_default_model(&self) -> Result<FeatureList<'static>, Error> {
+    fn find_default_model(&self) -> Result<Model, Error> {
         let mut conn = self.client.get_connection()?;
         let key = self.keys.default_model_key();
-        conn.deget(key).map(Option::unwrap_or_default)
+        conn.deget_versioned(key).map(Option::unwrap_or_default)
+    }
+
+    fn set_model(&self, part: &str, model: Model) -> Result<(), Error> {
+        let mut conn = self.client.get_connection()?;
+        let key = self.keys.model_key(part);
+        conn.serput_versioned(key, &model)?;
+        Ok(())
     }
 
-    fn find_model(&self, part: &str) -> Result<Option<FeatureList<'static>>, Error> {
+    fn find_model(&self, part: &str) -> Result<Option<Model>, Error> {
         let mut conn = self.client.get_connection()?;
         let key = self.keys.model_key(part);
-        conn.deget(key)
+        conn.deget_versioned(key)
+    }
+
+    fn model_version_save(&self, version: &ModelVersion) -> Result<(), Error> {
+        let mut conn = self.client.get_connection()?;
+        let key = self.keys.model_version_key(&version.part, version.id);
+        let list_key = self.keys.model_version_list_key(&version.part);
+        conn.serput_versioned(key, version)?;
+        let _: () = conn.rpush(list_key, version.id.to_string())?;
+        Ok(())
+    }
+
+    fn model_version_find(&self, part: &str, id: Uuid) -> Result<Option<ModelVersion>, Error> {
+        let mut conn = self.client.get_connection()?;
+        let key = self.keys.model_version_key(part, id);
+        conn.deget_versioned(key)
+    }
+
+    fn model_version_list(&self, part: &str) -> Result<Vec<Uuid>, Error> {
+        let mut conn = self.client.get_connection()?;
+        let key = self.keys.model_version_list_key(part);
+        let list: Vec<String> = conn.lrange(key, 0, -1)?;
+        Ok(list
+            .into_iter()
+            .flat_map(|i| i.parse::<Uuid>().ok())
+            .collect())
+    }
+
+    fn model_version_delete(&self, part: &str, id: Uuid) -> Result<(), Error> {
+        let mut conn = self.client.get_connection()?;
+        let key = self.keys.model_version_key(part, id);
+        let list_key = self.keys.model_version_list_key(part);
+        let _: () = redis::pipe()
+            .atomic()
+            .del(key)
+            .ignore()
+            .lrem(list_key, 0, id.to_string())
+            .ignore()
+            .query(&mut conn)?;
+        Ok(())
+    }
+
+    fn model_pointer_find(&self, part: &str) -> Result<ModelPointer, Error> {
+        let mut conn = self.client.get_connection()?;
+        let key = self.keys.model_pointer_key(part);
+        conn.deget(key).map(Option::unwrap_or_default)
+    }
+
+    fn model_pointer_set(&self, part: &str, pointer: &ModelPointer) -> Result<(), Error> {
+        let mut conn = self.client.get_connection()?;
+        let key = self.keys.model_pointer_key(part);
+        conn.serput(key, pointer)
     }
 
     fn model_activity_save(&self, part: &str, activity: &Activity) -> Result<(), Error> {
         let mut conn = self.client.get_connection()?;
         let key = self.keys.activity_key(part, activity.id);
-        let data = bincode::serialize(activity)?;
+        let data = format::encode(activity)?;
         let lifetime = if activity.chosen.is_some() {
             self.long_activity_lifetime
         } else {
@@ -55,30 +115,37 @@ impl ModelStore for RedisStorage {
     fn model_activity_load(&self, part: &str, id: Uuid) -> Result<Option<Activity>, Error> {
         self.client
             .get_connection()?
-            .deget(self.keys.activity_key(part, id))
+            .deget_versioned(self.keys.activity_key(part, id))
     }
 
-    fn model_activity_choose(&self, part: &str, id: Uuid, chosen: &[Uuid]) -> Result<(), Error> {
+    fn model_activity_choose(
+        &self,
+        part: &str,
+        id: Uuid,
+        chosen: &[Uuid],
+        event: Option<&str>,
+    ) -> Result<(), Error> {
         let mut conn = self.client.get_connection()?;
         let key = self.keys.activity_key(part, id);
         redis::transaction(&mut conn, &[&key], |conn, pipe| {
-            let data: Option<Activity> = conn
-                .get::<_, Option<Vec<u8>>>(&key)?
-                .and_then(|data| bincode::deserialize(&data).ok());
-
-            let mut data = if let Some(d) = data {
-                d
-            } else {
-                return Ok(Some(()));
+            let data = conn.get::<_, Option<Vec<u8>>>(&key)?;
+            let mut data = match data.map(|data| format::decode::<Activity>(&data)) {
+                Some(Ok(d)) => d,
+                Some(Err(e)) => {
+                    error!("could not read activity {}: {:?}", id, e);
+                    return Ok(Some(()));
+                }
+                None => return Ok(Some(())),
             };
             data.chosen = Some(chosen.to_owned());
-            let data = bincode::serialize(&data).expect("could not serialize activity?");
+            data.event = event.map(ToOwned::to_owned);
+            let data = format::encode(&data).expect("could not serialize activity?");
             pipe.set_ex(&key, data, self.long_activity_lifetime as usize)
                 .ignore()
                 .query(conn)
         })?;
 
-        let activity: Activity = bincode::deserialize(&conn.get::<_, Vec<u8>>(&key)?)?;
+        let activity: Activity = format::decode(&conn.get::<_, Vec<u8>>(&key)?)?;
 
         let local_key = self.keys.activity_list_key(part);
         let default_key = self.keys.default_activity_list_key();
@@ -94,6 +161,69 @@ impl ModelStore for RedisStorage {
         Ok(())
     }
 
+    fn model_activity_learn(&self, part: &str, id: Uuid) -> Result<Option<Activity>, Error> {
+        let mut conn = self.client.get_connection()?;
+        let key = self.keys.activity_key(part, id);
+        let mut learned = None;
+        let _: () = redis::transaction(&mut conn, &[&key], |conn, pipe| {
+            learned = None;
+            let mut data = match conn.deget_versioned::<Activity, _>(&key) {
+                Ok(Some(d)) if !d.learned => d,
+                Ok(_) => return Ok(Some(())),
+                Err(e) => {
+                    error!("could not read activity {}: {:?}", id, e);
+                    return Ok(Some(()));
+                }
+            };
+            data.learned = true;
+            let encoded = format::encode(&data).expect("could not serialize activity?");
+            learned = Some(data);
+            pipe.set_ex(&key, encoded, self.long_activity_lifetime as usize)
+                .ignore()
+                .query(conn)
+        })?;
+        Ok(learned)
+    }
+
+    fn model_stats_record(&self, part: &str, shown: &[Uuid], chosen: &[Uuid]) -> Result<(), Error> {
+        if shown.is_empty() && chosen.is_empty() {
+            return Ok(());
+        }
+
+        // Each item has a field in the hash for the number of times it was
+        // shown and the number of times it was chosen, so that recording
+        // doesn't have to read the statistics of the whole part.
+        let mut conn = self.client.get_connection()?;
+        let key = self.keys.model_stats_key(part);
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for id in shown {
+            pipe.hincr(&key, format!("{}:shown", id), 1).ignore();
+        }
+        for id in chosen {
+            pipe.hincr(&key, format!("{}:chosen", id), 1).ignore();
+        }
+        let _: () = pipe.query(&mut conn)?;
+        Ok(())
+    }
+
+    fn model_stats_find(&self, part: &str) -> Result<HashMap<Uuid, ItemStats>, Error> {
+        let mut conn = self.client.get_connection()?;
+        let key = self.keys.model_stats_key(part);
+        let fields: HashMap<String, u64> = conn.hgetall(key)?;
+        let mut stats = HashMap::<Uuid, ItemStats>::new();
+        for (field, count) in fields {
+            let mut split = field.splitn(2, ':');
+            let id = split.next().and_then(|id| id.parse().ok());
+            match (id, split.next()) {
+                (Some(id), Some("shown")) => stats.entry(id).or_default().shown = count,
+                (Some(id), Some("chosen")) => stats.entry(id).or_default().chosen = count,
+                _ => warn!("unknown item stats field {:?}", field),
+            }
+        }
+        Ok(stats)
+    }
+
     fn model_activity_pluck(&self) -> Result<Vec<Activity>, Error> {
         let mut conn = self.client.get_connection()?;
         let default_key = self.keys.default_activity_list_key();
@@ -110,7 +240,7 @@ impl ModelStore for RedisStorage {
         for item in items {
             let (part, id) = bincode::deserialize::<(String, Uuid)>(&item)?;
             let key = self.keys.activity_key(&part, id);
-            if let Some(activity) = conn.deget::<Activity, _>(key)? {
+            if let Some(activity) = conn.deget_versioned::<Activity, _>(key)? {
                 buf.push(activity);
             }
         }
@@ -119,6 +249,57 @@ impl ModelStore for RedisStorage {
         Ok(buf)
     }
 
+    fn model_activity_pluck_part(&self, part: &str) -> Result<Vec<Activity>, Error> {
+        let mut conn = self.client.get_connection()?;
+        let default_key = self.keys.default_activity_list_key();
+        let local_key = self.keys.activity_list_key(part);
+        let mut plucked = vec![];
+        let _: () = redis::transaction(&mut conn, &[&default_key], |conn, pipe| {
+            let items: Vec<Vec<u8>> = conn.lrange(&default_key, 0, -1)?;
+            plucked.clear();
+            for item in items {
+                match bincode::deserialize::<(String, Uuid)>(&item) {
+                    Ok((item_part, id)) if item_part == part => {
+                        pipe.lrem(&default_key, 1, &item[..]).ignore();
+                        plucked.push(id);
+                    }
+                    _ => {}
+                }
+            }
+            pipe.del(&local_key).ignore().query(conn)
+        })?;
+
+        let mut buf = Vec::new();
+        for id in plucked {
+            let key = self.keys.activity_key(part, id);
+            if let Some(activity) = conn.deget_versioned::<Activity, _>(key)? {
+                buf.push(activity);
+            }
+        }
+
+        Ok(buf)
+    }
+
+    fn model_activity_list_part(&self, part: &str) -> Result<Vec<Activity>, Error> {
+        let mut conn = self.client.get_connection()?;
+        let default_key = self.keys.default_activity_list_key();
+        let items: Vec<Vec<u8>> = conn.lrange(&default_key, 0, -1)?;
+        let mut buf = Vec::new();
+
+        for item in items {
+            let (item_part, id) = bincode::deserialize::<(String, Uuid)>(&item)?;
+            if item_part != part {
+                continue;
+            }
+            let key = self.keys.activity_key(part, id);
+            if let Some(activity) = conn.deget_versioned::<Activity, _>(key)? {
+                buf.push(activity);
+            }
+        }
+
+        Ok(buf)
+    }
+
     fn model_activity_delete_all<'p, Ids>(&self, id: Ids) -> Result<(), Error>
     where
         Ids: IntoIterator<Item = (&'p str, Uuid)>,
@@ -127,7 +308,27 @@ impl ModelStore for RedisStorage {
             .into_iter()
             .map(|(p, u)| self.keys.activity_key(p, u))
             .collect::<Vec<_>>();
-        self.client.get_connection()?.del(keys)?;
+        let _: () = self.client.get_connection()?.del(keys)?;
+        Ok(())
+    }
+
+    fn model_activity_restore<'p, Ids>(&self, ids: Ids) -> Result<(), Error>
+    where
+        Ids: IntoIterator<Item = (&'p str, Uuid)>,
+    {
+        let mut conn = self.client.get_connection()?;
+        for (part, id) in ids.into_iter() {
+            let local_key = self.keys.activity_list_key(part);
+            let default_key = self.keys.default_activity_list_key();
+            push_activity_list(
+                &mut conn,
+                (local_key, default_key),
+                self.activity_list_lifetime,
+                self.activity_list_length,
+                part,
+                id,
+            )?;
+        }
         Ok(())
     }
 }
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use crate::storage::core::models::format::{self, Versioned};
 use aerospike::errors::{Error as AerospikeError, ErrorKind as AerospikeErrorKind};
 use aerospike::{FloatValue, Record, ResultCode, Value};
 use failure::Error;
@@ -83,6 +84,7 @@ impl ValueExt for Value {
 
 pub(super) trait RecordExt {
     fn deserialize_bin<'s, T: Deserialize<'s>>(&'s self, bin: &str) -> Result<Option<T>, Error>;
+    fn decode_bin<T: Versioned>(&self, bin: &str) -> Result<Option<T>, Error>;
 }
 
 impl RecordExt for Record {
@@ -94,6 +96,14 @@ impl RecordExt for Record {
             .transpose()
             .map_err(Error::from)
     }
+
+    fn decode_bin<T: Versioned>(&self, bin: &str) -> Result<Option<T>, Error> {
+        self.bins
+            .get(bin)
+            .and_then(|bin| bin.as_blob())
+            .map(format::decode)
+            .transpose()
+    }
 }
 
 impl RecordExt for Option<Record> {
@@ -103,4 +113,11 @@ impl RecordExt for Option<Record> {
             None => Ok(None),
         }
     }
+
+    fn decode_bin<T: Versioned>(&self, bin: &str) -> Result<Option<T>, Error> {
+        match self {
+            Some(r) => r.decode_bin(bin),
+            None => Ok(None),
+        }
+    }
 }
@@ -84,6 +84,38 @@ impl Keys {
         as_key!(&self.model_namespace[..], "models", "model:default")
     }
 
+    pub(super) fn model_version_key(&self, part: &str, id: Uuid) -> Key {
+        as_key!(
+            &self.model_namespace[..],
+            "models:versions",
+            format!("version:{}:{}", part, id)
+        )
+    }
+
+    pub(super) fn model_version_list_key(&self, part: &str) -> Key {
+        as_key!(
+            &self.model_namespace[..],
+            "models:versions",
+            format!("version-list:{}", part)
+        )
+    }
+
+    pub(super) fn model_pointer_key(&self, part: &str) -> Key {
+        as_key!(
+            &self.model_namespace[..],
+            "models",
+            format!("model:pointer:{}", part)
+        )
+    }
+
+    pub(super) fn model_stats_key(&self, part: &str) -> Key {
+        as_key!(
+            &self.model_namespace[..],
+            "models",
+            format!("model:stats:{}", part)
+        )
+    }
+
     pub(super) fn activity_key(&self, part: &str, id: Uuid) -> Key {
         as_key!(
             &self.activity_namespace[..],
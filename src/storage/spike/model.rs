@@ -1,42 +1,130 @@
 use super::ext::RecordExt;
 use super::ext::{ResultExt, ValueExt};
 use super::ModelStore;
-use super::SpikeStorage;
+use super::{read_modify_write, SpikeStorage};
+use crate::storage::core::models::format;
 use crate::storage::Activity;
-use crate::storage::FeatureList;
+use crate::storage::ItemStats;
+use crate::storage::Model;
+use crate::storage::{ModelPointer, ModelVersion};
+use aerospike::errors::{Error as AerospikeError, ErrorKind as AerospikeErrorKind};
 use aerospike::{
-    BatchPolicy, BatchRead, Bin, Bins, Client, Expiration, Key, ReadPolicy, Value, WritePolicy,
+    BatchPolicy, BatchRead, Bin, Bins, Client, Expiration, GenerationPolicy, Key, ReadPolicy,
+    ResultCode, Value, WritePolicy,
 };
 use failure::{Error, SyncFailure};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 impl ModelStore for SpikeStorage {
-    fn set_default_model(&self, list: FeatureList<'_>) -> Result<(), Error> {
+    fn set_default_model(&self, model: Model) -> Result<(), Error> {
         let key = self.keys.default_model_key();
-        let bin = bincode::serialize(&list)?;
+        let bin = format::encode(&model)?;
         self.client
             .put(&Default::default(), &key, &[Bin::new("data", bin.into())])
             .map_err(SyncFailure::new)?;
         Ok(())
     }
 
-    fn find_default_model(&self) -> Result<FeatureList<'static>, Error> {
+    fn find_default_model(&self) -> Result<Model, Error> {
         let key = self.keys.default_model_key();
-        let list = self
-            .get(&key, ["data"])?
-            .deserialize_bin::<FeatureList<'static>>("data")?;
+        let list = self.get(&key, ["data"])?.decode_bin::<Model>("data")?;
         Ok(list.unwrap_or_default())
     }
 
-    fn find_model(&self, part: &str) -> Result<Option<FeatureList<'static>>, Error> {
+    fn set_model(&self, part: &str, model: Model) -> Result<(), Error> {
+        let key = self.keys.model_key(part);
+        let bin = format::encode(&model)?;
+        self.client
+            .put(&Default::default(), &key, &[Bin::new("data", bin.into())])
+            .map_err(SyncFailure::new)?;
+        Ok(())
+    }
+
+    fn find_model(&self, part: &str) -> Result<Option<Model>, Error> {
         let key = self.keys.model_key(part);
-        self.get(&key, ["data"])?
-            .deserialize_bin::<FeatureList<'static>>("data")
+        self.get(&key, ["data"])?.decode_bin::<Model>("data")
+    }
+
+    fn model_version_save(&self, version: &ModelVersion) -> Result<(), Error> {
+        use aerospike::operations as ops;
+        let key = self.keys.model_version_key(&version.part, version.id);
+        let bin = format::encode(version)?;
+        self.client
+            .put(&Default::default(), &key, &[Bin::new("data", bin.into())])
+            .map_err(SyncFailure::new)?;
+
+        let key = self.keys.model_version_list_key(&version.part);
+        let value = Value::String(version.id.to_string());
+        let push = [ops::lists::append("list", &value)];
+        self.client
+            .operate(&Default::default(), &key, &push)
+            .map_err(SyncFailure::new)?;
+        Ok(())
+    }
+
+    fn model_version_find(&self, part: &str, id: Uuid) -> Result<Option<ModelVersion>, Error> {
+        let key = self.keys.model_version_key(part, id);
+        self.get(&key, ["data"])?.decode_bin::<ModelVersion>("data")
+    }
+
+    fn model_version_list(&self, part: &str) -> Result<Vec<Uuid>, Error> {
+        let key = self.keys.model_version_list_key(part);
+        let record = self.get(&key, ["list"])?;
+        let list = record
+            .as_ref()
+            .and_then(|r| r.bins.get("list"))
+            .and_then(|v| v.as_list())
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|v| v.as_str())
+            .flat_map(|v| v.parse::<Uuid>().ok())
+            .collect();
+        Ok(list)
+    }
+
+    fn model_version_delete(&self, part: &str, id: Uuid) -> Result<(), Error> {
+        let key = self.keys.model_version_key(part, id);
+        self.client
+            .delete(&WritePolicy::default(), &key)
+            .map_err(SyncFailure::new)?;
+
+        let key = self.keys.model_version_list_key(part);
+        let id = id.to_string();
+        read_modify_write(&self.client, &key, ["list"], |record| {
+            let list = record
+                .as_ref()
+                .and_then(|r| r.bins.get("list"))
+                .and_then(|v| v.as_list())
+                .unwrap_or_default()
+                .iter()
+                .filter(|v| v.as_str() != Some(&id[..]))
+                .cloned()
+                .collect::<Vec<_>>();
+            Ok(vec![Bin::new("list", Value::List(list))])
+        })
+    }
+
+    fn model_pointer_find(&self, part: &str) -> Result<ModelPointer, Error> {
+        let key = self.keys.model_pointer_key(part);
+        let pointer = self
+            .get(&key, ["data"])?
+            .deserialize_bin::<ModelPointer>("data")?;
+        Ok(pointer.unwrap_or_default())
+    }
+
+    fn model_pointer_set(&self, part: &str, pointer: &ModelPointer) -> Result<(), Error> {
+        let key = self.keys.model_pointer_key(part);
+        let bin = bincode::serialize(pointer)?;
+        self.client
+            .put(&Default::default(), &key, &[Bin::new("data", bin.into())])
+            .map_err(SyncFailure::new)?;
+        Ok(())
     }
 
     fn model_activity_save(&self, part: &str, activity: &Activity) -> Result<(), Error> {
         let key = self.keys.activity_key(part, activity.id);
-        let data = bincode::serialize(activity)?;
+        let data = format::encode(activity)?;
         let lifetime = if activity.chosen.is_some() {
             self.long_activity_lifetime
         } else {
@@ -64,14 +152,19 @@ impl ModelStore for SpikeStorage {
 
     fn model_activity_load(&self, part: &str, id: Uuid) -> Result<Option<Activity>, Error> {
         let key = self.keys.activity_key(part, id);
-        self.get(&key, ["data"])?
-            .deserialize_bin::<Activity>("data")
+        self.get(&key, ["data"])?.decode_bin::<Activity>("data")
     }
 
-    fn model_activity_choose(&self, part: &str, id: Uuid, chosen: &[Uuid]) -> Result<(), Error> {
+    fn model_activity_choose(
+        &self,
+        part: &str,
+        id: Uuid,
+        chosen: &[Uuid],
+        event: Option<&str>,
+    ) -> Result<(), Error> {
         let key = self.keys.activity_key(part, id);
         let record = self.get(&key, ["data"])?;
-        let data = record.deserialize_bin::<Activity>("data")?;
+        let data = record.decode_bin::<Activity>("data")?;
         let mut data = if let Some(d) = data {
             d
         } else {
@@ -79,7 +172,8 @@ impl ModelStore for SpikeStorage {
         };
 
         data.chosen = Some(chosen.to_owned());
-        let data = bincode::serialize(&data)?;
+        data.event = event.map(ToOwned::to_owned);
+        let data = format::encode(&data)?;
         let bins = [Bin::new("data", data.into())];
 
         let policy = WritePolicy::new(
@@ -93,6 +187,93 @@ impl ModelStore for SpikeStorage {
         Ok(())
     }
 
+    fn model_activity_learn(&self, part: &str, id: Uuid) -> Result<Option<Activity>, Error> {
+        let key = self.keys.activity_key(part, id);
+        let record = self.get(&key, ["data"])?;
+        let mut data = match record.decode_bin::<Activity>("data")? {
+            Some(d) if !d.learned => d,
+            _ => return Ok(None),
+        };
+
+        data.learned = true;
+        let bins = [Bin::new("data", format::encode(&data)?.into())];
+        // The write only succeeds if the activity wasn't changed since it
+        // was read, so that it's only ever learned from once.
+        let policy = WritePolicy {
+            generation_policy: GenerationPolicy::ExpectGenEqual,
+            generation: record.map(|r| r.generation).unwrap_or(0),
+            expiration: Expiration::Seconds(self.long_activity_lifetime),
+            ..WritePolicy::default()
+        };
+
+        match self.client.put(&policy, &key, &bins) {
+            Ok(()) => Ok(Some(data)),
+            Err(AerospikeError(
+                AerospikeErrorKind::ServerError(ResultCode::GenerationError),
+                _,
+            )) => Ok(None),
+            Err(e) => Err(SyncFailure::new(e).into()),
+        }
+    }
+
+    fn model_stats_record(&self, part: &str, shown: &[Uuid], chosen: &[Uuid]) -> Result<(), Error> {
+        use aerospike::operations::{self as ops, MapPolicy};
+
+        if shown.is_empty() && chosen.is_empty() {
+            return Ok(());
+        }
+
+        // The number of times each item was shown and chosen are kept in a
+        // map for each, keyed by the item, which are incremented in place.
+        let key = self.keys.model_stats_key(part);
+        let policy = MapPolicy::default();
+        let one = Value::from(1);
+        let shown = shown
+            .iter()
+            .map(|id| Value::from(id.to_string()))
+            .collect::<Vec<_>>();
+        let chosen = chosen
+            .iter()
+            .map(|id| Value::from(id.to_string()))
+            .collect::<Vec<_>>();
+        let increments = shown
+            .iter()
+            .map(|id| ops::maps::increment_value(&policy, "shown", id, &one))
+            .chain(
+                chosen
+                    .iter()
+                    .map(|id| ops::maps::increment_value(&policy, "chosen", id, &one)),
+            )
+            .collect::<Vec<_>>();
+        self.client
+            .operate(&WritePolicy::default(), &key, &increments)
+            .map_err(SyncFailure::new)?;
+        Ok(())
+    }
+
+    fn model_stats_find(&self, part: &str) -> Result<HashMap<Uuid, ItemStats>, Error> {
+        let key = self.keys.model_stats_key(part);
+        let record = self.get(&key, ["shown", "chosen"])?;
+        let mut stats = HashMap::<Uuid, ItemStats>::new();
+        let counts = |bin: &str| {
+            let map = record.as_ref().and_then(|r| r.bins.get(bin));
+            map.and_then(|v| v.as_hash())
+                .into_iter()
+                .flatten()
+                .flat_map(|(id, count)| {
+                    let id = id.as_str().and_then(|id| id.parse::<Uuid>().ok())?;
+                    Some((id, count.as_u64()?))
+                })
+        };
+        for (id, count) in counts("shown") {
+            stats.entry(id).or_default().shown = count;
+        }
+        for (id, count) in counts("chosen") {
+            stats.entry(id).or_default().chosen = count;
+        }
+        Ok(stats)
+    }
+
     fn model_activity_pluck(&self) -> Result<Vec<Activity>, Error> {
         let default_key = self.keys.default_activity_list_key();
         let result = self
@@ -140,12 +321,47 @@ impl ModelStore for SpikeStorage {
                     .bins
                     .get("data")
                     .and_then(|data| data.as_blob())
-                    .and_then(|data| bincode::deserialize::<Activity>(data).ok())
+                    .and_then(|data| format::decode::<Activity>(data).ok())
             })
             .collect::<Vec<_>>();
         Ok(result)
     }
 
+    fn model_activity_pluck_part(&self, part: &str) -> Result<Vec<Activity>, Error> {
+        let default_key = self.keys.default_activity_list_key();
+        let mut plucked = vec![];
+        read_modify_write(&self.client, &default_key, ["list"], |record| {
+            let list = record
+                .as_ref()
+                .and_then(|r| r.bins.get("list"))
+                .and_then(|v| v.as_list())
+                .unwrap_or_default();
+            let (found, rest) = list
+                .iter()
+                .cloned()
+                .partition::<Vec<_>, _>(|value| activity_list_entry(value).0 == Some(part));
+            plucked = found;
+            Ok(vec![Bin::new("list", Value::List(rest))])
+        })?;
+
+        self.read_activities(&plucked)
+    }
+
+    fn model_activity_list_part(&self, part: &str) -> Result<Vec<Activity>, Error> {
+        let default_key = self.keys.default_activity_list_key();
+        let record = self.get(&default_key, ["list"])?;
+        let listed = record
+            .as_ref()
+            .and_then(|r| r.bins.get("list"))
+            .and_then(|v| v.as_list())
+            .unwrap_or_default()
+            .iter()
+            .filter(|value| activity_list_entry(value).0 == Some(part))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.read_activities(&listed)
+    }
+
     fn model_activity_delete_all<'p, Ids>(&self, id: Ids) -> Result<(), Error>
     where
         Ids: IntoIterator<Item = (&'p str, Uuid)>,
@@ -160,6 +376,66 @@ impl ModelStore for SpikeStorage {
         }
         Ok(())
     }
+
+    fn model_activity_restore<'p, Ids>(&self, ids: Ids) -> Result<(), Error>
+    where
+        Ids: IntoIterator<Item = (&'p str, Uuid)>,
+    {
+        for (part, id) in ids.into_iter() {
+            let local_key = self.keys.activity_list_key(part);
+            let default_key = self.keys.default_activity_list_key();
+            push_activity_list(
+                &self.client,
+                (local_key, default_key),
+                self.list_activity_lifetime,
+                self.list_activity_length,
+                part,
+                id,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl SpikeStorage {
+    /// Reads the activities of the entries of an activity list.
+    fn read_activities(&self, entries: &[Value]) -> Result<Vec<Activity>, Error> {
+        let bins = Bins::from(["data"]);
+        let items = entries
+            .iter()
+            .flat_map(|value| match activity_list_entry(value) {
+                (Some(part), Some(id)) => Some(self.keys.activity_key(part, id)),
+                _ => None,
+            })
+            .map(|key| BatchRead {
+                key,
+                bins: &bins,
+                record: None,
+            })
+            .collect::<Vec<_>>();
+        let result = self
+            .client
+            .batch_get(&BatchPolicy::default(), items)
+            .map_err(SyncFailure::new)?;
+
+        let result = result
+            .into_iter()
+            .flat_map(|read| read.record)
+            .flat_map(|record| record.decode_bin::<Activity>("data").ok())
+            .flatten()
+            .collect::<Vec<_>>();
+        Ok(result)
+    }
+}
+
+fn activity_list_entry(value: &Value) -> (Option<&str>, Option<Uuid>) {
+    let item = value.as_list().unwrap_or_default();
+    let part = item.first().and_then(|part| part.as_str());
+    let id = item
+        .get(1)
+        .and_then(|id| id.as_str())
+        .and_then(|v| v.parse::<Uuid>().ok());
+    (part, id)
 }
 
 fn push_activity_list(